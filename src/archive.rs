@@ -0,0 +1,383 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use refs::*;
+
+// The format: the magic bytes and a version, then the values in the order
+// they were pushed. Integers are LEB128 varints, signed ones zigzag-encoded
+// first. A node behind a `Ref` is written once, the first time it is met, and
+// gets the next id; later meetings only write its id, so that the reader can
+// hand out the node it built the first time.
+const MAGIC : &'static [u8] = b"GIFT";
+const VERSION : u8 = 1;
+
+// The tags of a link to a node; tags from `SHARED` up refer to the node with
+// id `tag - SHARED`.
+const NONE : u64 = 0;
+const NEW : u64 = 1;
+const SHARED : u64 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    BadHeader,
+    UnexpectedEnd,
+    /// A reference to a node that has not been read.
+    BadReference(u64),
+    /// A reference to a node of another type.
+    TypeMismatch(u64),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArchiveError::BadHeader       => write!(f, "archive: not an archive, or of another version"),
+            ArchiveError::UnexpectedEnd   => write!(f, "archive: unexpected end of input"),
+            ArchiveError::BadReference(i) => write!(f, "archive: reference to unknown node {}", i),
+            ArchiveError::TypeMismatch(i) => write!(f, "archive: node {} has another type", i),
+            ArchiveError::Invalid(msg)    => write!(f, "archive: {}", msg)
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+/// Values that can be written to an `Archive`. The collections write each of
+/// their nodes once per archive, however many of the archived values share
+/// it, and reading them back shares the nodes in the same way.
+pub trait Archivable: Sized {
+    fn archive(&self, a: &mut Archive);
+    fn unarchive(r: &mut ArchiveReader) -> Result<Self, ArchiveError>;
+}
+
+/// Writes a set of values in a compact binary format that preserves the
+/// sharing between them.
+pub struct Archive {
+    buf  : Vec<u8>,
+    // node addresses and their ids
+    ids  : HashMap<usize, u64>,
+    // keeps the written nodes alive, so that their addresses are not reused
+    // by nodes that are written later
+    keep : Vec<Box<dyn Any>>,
+}
+
+impl Archive {
+    pub fn new() -> Archive {
+        let mut buf = MAGIC.to_vec();
+        buf.push(VERSION);
+        Archive { buf: buf, ids: HashMap::new(), keep: vec![] }
+    }
+
+    pub fn push<T: Archivable>(&mut self, x: &T) {
+        x.archive(self)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_varint(&mut self, x: u64) {
+        let mut x = x;
+        while x >= 0x80 {
+            self.buf.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        self.buf.push(x as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Writes a link to no node.
+    pub fn write_none(&mut self) {
+        self.write_varint(NONE)
+    }
+
+    /// Writes a link to `node`. Returns true if the node is new to the
+    /// archive, in which case the caller writes its contents next.
+    pub fn begin_node<X: Clone+'static>(&mut self, node: &Ref<X>) -> bool {
+        let addr = &*node._ptr as *const X as usize;
+        if let Some(&id) = self.ids.get(&addr) {
+            self.write_varint(SHARED + id);
+            return false
+        }
+        let id = self.ids.len() as u64;
+        self.ids.insert(addr, id);
+        self.keep.push(Box::new(node.clone()));
+        self.write_varint(NEW);
+        true
+    }
+}
+
+/// A link read from an archive, see `Archive::begin_node`.
+pub enum Link<X> {
+    None,
+    /// A node that is read next; pass it to `fill_node` once it is built.
+    New(u64),
+    Shared(Ref<X>),
+}
+
+/// Reads the values of an archive, in the order they were written.
+pub struct ArchiveReader<'a> {
+    buf   : &'a [u8],
+    pos   : usize,
+    nodes : Vec<Option<Box<dyn Any>>>,
+}
+
+impl <'a> ArchiveReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<ArchiveReader<'a>, ArchiveError> {
+        if bytes.len() <= MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC || bytes[MAGIC.len()] != VERSION {
+            return Err(ArchiveError::BadHeader)
+        }
+        Ok(ArchiveReader { buf: bytes, pos: MAGIC.len() + 1, nodes: vec![] })
+    }
+
+    pub fn read<T: Archivable>(&mut self) -> Result<T, ArchiveError> {
+        T::unarchive(self)
+    }
+
+    /// Whether all values have been read.
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, ArchiveError> {
+        let mut ret = 0;
+        let mut shift = 0;
+        loop {
+            let b = *self.buf.get(self.pos).ok_or(ArchiveError::UnexpectedEnd)?;
+            self.pos += 1;
+            // the tenth byte only has room for the top bit
+            if shift >= 64 || (shift == 63 && b & 0x7e != 0) {
+                return Err(ArchiveError::Invalid("varint too long"))
+            }
+            ret |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(ret)
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], ArchiveError> {
+        let len = self.read_varint()? as usize;
+        if self.buf.len() - self.pos < len {
+            return Err(ArchiveError::UnexpectedEnd)
+        }
+        let ret = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(ret)
+    }
+
+    pub fn read_link<X: Clone+'static>(&mut self) -> Result<Link<X>, ArchiveError> {
+        match self.read_varint()? {
+            NONE => Ok(Link::None),
+            NEW  => {
+                self.nodes.push(None);
+                Ok(Link::New(self.nodes.len() as u64 - 1))
+            }
+            tag  => {
+                let id = tag - SHARED;
+                match self.nodes.get(id as usize) {
+                    Some(&Some(ref node)) => match node.downcast_ref::<Ref<X>>() {
+                        Some(node) => Ok(Link::Shared(node.clone())),
+                        None       => Err(ArchiveError::TypeMismatch(id))
+                    },
+                    _ => Err(ArchiveError::BadReference(id))
+                }
+            }
+        }
+    }
+
+    /// Records the node built for the link `Link::New(id)`.
+    pub fn fill_node<X: Clone+'static>(&mut self, id: u64, node: &Ref<X>) {
+        self.nodes[id as usize] = Some(Box::new(node.clone()));
+    }
+}
+
+macro_rules! archivable_unsigned {
+    ($($t:ty),*) => { $(
+        impl Archivable for $t {
+            fn archive(&self, a: &mut Archive) {
+                a.write_varint(*self as u64)
+            }
+
+            fn unarchive(r: &mut ArchiveReader) -> Result<$t, ArchiveError> {
+                let x = r.read_varint()?;
+                if x > <$t>::max_value() as u64 {
+                    return Err(ArchiveError::Invalid("integer out of range"))
+                }
+                Ok(x as $t)
+            }
+        }
+    )* }
+}
+
+macro_rules! archivable_signed {
+    ($($t:ty),*) => { $(
+        impl Archivable for $t {
+            fn archive(&self, a: &mut Archive) {
+                let x = *self as i64;
+                a.write_varint(((x << 1) ^ (x >> 63)) as u64)
+            }
+
+            fn unarchive(r: &mut ArchiveReader) -> Result<$t, ArchiveError> {
+                let x = r.read_varint()?;
+                let x = ((x >> 1) as i64) ^ -((x & 1) as i64);
+                if x < <$t>::min_value() as i64 || x > <$t>::max_value() as i64 {
+                    return Err(ArchiveError::Invalid("integer out of range"))
+                }
+                Ok(x as $t)
+            }
+        }
+    )* }
+}
+
+archivable_unsigned!(u8, u16, u32, u64, usize);
+archivable_signed!(i8, i16, i32, i64, isize);
+
+impl Archivable for bool {
+    fn archive(&self, a: &mut Archive) {
+        a.write_varint(*self as u64)
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<bool, ArchiveError> {
+        match r.read_varint()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ArchiveError::Invalid("bad bool"))
+        }
+    }
+}
+
+impl Archivable for char {
+    fn archive(&self, a: &mut Archive) {
+        a.write_varint(*self as u64)
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<char, ArchiveError> {
+        let x = r.read_varint()?;
+        if x > u32::max_value() as u64 {
+            return Err(ArchiveError::Invalid("bad char"))
+        }
+        ::std::char::from_u32(x as u32).ok_or(ArchiveError::Invalid("bad char"))
+    }
+}
+
+impl Archivable for String {
+    fn archive(&self, a: &mut Archive) {
+        a.write_bytes(self.as_bytes())
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<String, ArchiveError> {
+        let bytes = r.read_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ArchiveError::Invalid("bad utf-8"))
+    }
+}
+
+impl <T: Archivable> Archivable for Option<T> {
+    fn archive(&self, a: &mut Archive) {
+        match *self {
+            None        => a.write_varint(0),
+            Some(ref x) => {
+                a.write_varint(1);
+                x.archive(a)
+            }
+        }
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<Option<T>, ArchiveError> {
+        match r.read_varint()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::unarchive(r)?)),
+            _ => Err(ArchiveError::Invalid("bad option"))
+        }
+    }
+}
+
+impl <A: Archivable, B: Archivable> Archivable for (A, B) {
+    fn archive(&self, a: &mut Archive) {
+        self.0.archive(a);
+        self.1.archive(a)
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<(A, B), ArchiveError> {
+        let x = A::unarchive(r)?;
+        let y = B::unarchive(r)?;
+        Ok((x, y))
+    }
+}
+
+impl <T: Archivable> Archivable for Vec<T> {
+    fn archive(&self, a: &mut Archive) {
+        a.write_varint(self.len() as u64);
+        for x in self {
+            x.archive(a)
+        }
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<Vec<T>, ArchiveError> {
+        let len = r.read_varint()? as usize;
+        // every element takes at least a byte, so a bogus length can not make
+        // this allocate more than the input
+        let mut ret = Vec::with_capacity(len.min(r.buf.len() - r.pos));
+        for _ in 0..len {
+            ret.push(T::unarchive(r)?);
+        }
+        Ok(ret)
+    }
+}
+
+
+#[test]
+fn archive_primitives() {
+    let mut a = Archive::new();
+    a.push(&300u32);
+    a.push(&-5i64);
+    a.push(&i32::min_value());
+    a.push(&true);
+    a.push(&'ß');
+    a.push(&"hello".to_string());
+    a.push(&vec![(1u8, Some(2i16)), (3, None)]);
+    let bytes = a.into_bytes();
+
+    let mut r = ArchiveReader::new(&bytes).unwrap();
+    assert_eq!(Ok(300u32), r.read());
+    assert_eq!(Ok(-5i64), r.read());
+    assert_eq!(Ok(i32::min_value()), r.read());
+    assert_eq!(Ok(true), r.read());
+    assert_eq!(Ok('ß'), r.read());
+    assert_eq!(Ok("hello".to_string()), r.read());
+    assert_eq!(Ok(vec![(1u8, Some(2i16)), (3, None)]), r.read());
+    assert!(r.is_empty());
+    assert_eq!(Err(ArchiveError::UnexpectedEnd), r.read::<u8>());
+}
+
+#[test]
+fn archive_errors() {
+    assert!(ArchiveReader::new(b"nope").is_err());
+    let mut a = Archive::new();
+    a.push(&1000u32);
+    let bytes = a.into_bytes();
+    let mut r = ArchiveReader::new(&bytes).unwrap();
+    assert_eq!(Err(ArchiveError::Invalid("integer out of range")), r.read::<u8>());
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.push((SHARED + 7) as u8);
+    let mut r = ArchiveReader::new(&bytes).unwrap();
+    assert!(match r.read_link::<i32>() { Err(ArchiveError::BadReference(7)) => true, _ => false });
+
+    // ten bytes hold 64 bits only if the last one is 0 or 1
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&[0xff; 9]);
+    bytes.push(0x01);
+    assert_eq!(Ok(u64::max_value()), ArchiveReader::new(&bytes).unwrap().read_varint());
+    *bytes.last_mut().unwrap() = 0x02;
+    assert_eq!(Err(ArchiveError::Invalid("varint too long")), ArchiveReader::new(&bytes).unwrap().read_varint());
+}
@@ -0,0 +1,268 @@
+use std::borrow::Borrow;
+use std::cmp::{min, max};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{Iterator, FromIterator};
+
+use hashset;
+use hashset::HashSet;
+use heap::Heap;
+use stats::{MemoryStats, NodeVisitor};
+use show::Disp;
+
+// An element with its multiplicity. Entries hash and compare by the element
+// alone, so that the set of entries can be searched by element.
+#[derive(Clone, Debug)]
+struct Entry<T> {
+    elt   : T,
+    count : usize,
+}
+
+impl <T: Hash> Hash for Entry<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.elt.hash(state)
+    }
+}
+
+impl <T: PartialEq> PartialEq for Entry<T> {
+    fn eq(&self, other: &Entry<T>) -> bool {
+        self.elt == other.elt
+    }
+}
+
+impl <T: Eq> Eq for Entry<T> {}
+
+impl <T> Borrow<T> for Entry<T> {
+    fn borrow(&self) -> &T {
+        &self.elt
+    }
+}
+
+/// A persistent multiset, which counts how often each element was inserted.
+/// Updates and lookups take O(log n) in the number of distinct elements, and
+/// clones are O(1).
+#[derive(Clone)]
+pub struct Bag<T: Hash+Eq+Clone> {
+    entries : HashSet<Entry<T>>,
+    total   : usize,
+}
+
+impl <T: Hash+Eq+Clone> Bag<T> {
+    pub fn new() -> Bag<T> {
+        Bag { entries: HashSet::new(), total: 0 }
+    }
+
+    pub fn insert(&mut self, x: T) {
+        self.insert_n(x, 1)
+    }
+
+    /// Adds `n` occurrences of `x`.
+    pub fn insert_n(&mut self, x: T, n: usize) {
+        if n > 0 {
+            let count = self.count(&x);
+            self.set_count(x, count + n);
+        }
+    }
+
+    /// Removes one occurrence of `x`; returns false if there was none.
+    pub fn remove_one(&mut self, x: &T) -> bool {
+        match self.count(x) {
+            0     => false,
+            count => {
+                self.set_count(x.clone(), count - 1);
+                true
+            }
+        }
+    }
+
+    pub fn count(&self, x: &T) -> usize {
+        self.entries.get(x).map_or(0, |entry| entry.count)
+    }
+
+    fn set_count(&mut self, x: T, count: usize) {
+        self.total -= self.count(&x);
+        self.total += count;
+        self.entries.remove(&x);
+        if count > 0 {
+            self.entries.insert(Entry { elt: x, count: count });
+        }
+    }
+
+    /// The number of different elements.
+    pub fn distinct_len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The number of elements, counting each occurrence.
+    pub fn total_len(&self) -> usize {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Iterates over the distinct elements and their counts, in no
+    /// particular order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { iter: self.entries.iter() }
+    }
+
+    /// The `k` most frequent elements with their counts, most frequent first.
+    /// Ties are broken arbitrarily. Runs in O(n log k).
+    pub fn most_common(&self, k: usize) -> Vec<(&T, usize)> {
+        let entries : Vec<_> = self.iter().collect();
+        // a min-heap of the k largest counts seen so far
+        let mut heap = Heap::new();
+        let mut len = 0;
+        for (i, &(_, count)) in entries.iter().enumerate() {
+            heap.insert((count, i));
+            len += 1;
+            if len > k {
+                heap.pop_min();
+                len -= 1;
+            }
+        }
+        let mut ret : Vec<_> = heap.to_iter().map(|(_, i)| entries[i]).collect();
+        ret.reverse();
+        ret
+    }
+
+    /// Each element with the larger of its counts in `self` and `other`.
+    pub fn union(&self, other: &Bag<T>) -> Bag<T> {
+        let mut ret = self.clone();
+        for (x, count) in other.iter() {
+            let mine = ret.count(x);
+            ret.set_count(x.clone(), max(mine, count));
+        }
+        ret
+    }
+
+    /// Each element with the smaller of its counts in `self` and `other`.
+    pub fn intersection(&self, other: &Bag<T>) -> Bag<T> {
+        let mut ret = Bag::new();
+        for (x, count) in self.iter() {
+            let theirs = other.count(x);
+            if theirs > 0 {
+                ret.insert_n(x.clone(), min(count, theirs));
+            }
+        }
+        ret
+    }
+}
+
+impl <T: Hash+Eq+Clone> PartialEq for Bag<T> {
+    fn eq(&self, other: &Bag<T>) -> bool {
+        self.total == other.total && self.distinct_len() == other.distinct_len()
+            && self.iter().all(|(x, count)| other.count(x) == count)
+    }
+}
+
+impl <T: Hash+Eq+Clone> Eq for Bag<T> {}
+
+impl <T: Hash+Eq+Clone> MemoryStats for Bag<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.entries.visit_nodes(v)
+    }
+}
+
+/// `{"a": 2, "b": 1}`, elements with their counts, or with `{:#?}` the set
+/// of entries.
+impl <T: Hash+Eq+Clone+fmt::Debug> fmt::Debug for Bag<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Bag").field("total", &self.total).field("entries", &self.entries).finish()
+        }
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl <T: Hash+Eq+Clone+fmt::Display> fmt::Display for Bag<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter().map(|(x, n)| (Disp(x), n))).finish()
+    }
+}
+
+impl <T: Hash+Eq+Clone> FromIterator<T> for Bag<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Bag<T> {
+        let mut ret = Bag::new();
+        for x in iter {
+            ret.insert(x);
+        }
+        ret
+    }
+}
+
+pub struct Iter<'a, T: Clone+'a> {
+    iter : hashset::Iter<'a, Entry<T>>,
+}
+
+impl <'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = (&'a T, usize);
+    fn next(&mut self) -> Option<(&'a T, usize)> {
+        self.iter.next().map(|entry| (&entry.elt, entry.count))
+    }
+}
+
+
+#[test]
+fn bag_counts() {
+    let mut b = Bag::new();
+    assert!(b.is_empty());
+    b.insert("a");
+    b.insert("b");
+    b.insert("a");
+    b.insert_n("c", 3);
+    b.insert_n("d", 0);
+    assert_eq!(2, b.count(&"a"));
+    assert_eq!(3, b.count(&"c"));
+    assert_eq!(0, b.count(&"d"));
+    assert_eq!(3, b.distinct_len());
+    assert_eq!(6, b.total_len());
+
+    let snapshot = b.clone();
+    assert!(b.remove_one(&"b"));
+    assert!(!b.remove_one(&"b"));
+    assert!(b.remove_one(&"a"));
+    assert_eq!(2, b.distinct_len());
+    assert_eq!(4, b.total_len());
+    assert_eq!(1, snapshot.count(&"b"));
+    assert_eq!(6, snapshot.total_len());
+}
+
+#[test]
+fn bag_fmt() {
+    let mut b = Bag::new();
+    assert_eq!("{}", format!("{:?}", b));
+    b.insert_n("a", 2);
+    assert_eq!("{\"a\": 2}", format!("{:?}", b));
+    assert_eq!("{a: 2}", format!("{}", b));
+    assert!(format!("{:#?}", b).starts_with("Bag {\n    total: 2,"));
+}
+
+#[test]
+fn bag_most_common() {
+    let b : Bag<char> = "mississippi".chars().collect();
+    assert_eq!(3, b.most_common(3).len());
+    let top = b.most_common(2);
+    assert_eq!(4, top[0].1);
+    assert_eq!(4, top[1].1);
+    assert_eq!(vec![(&'p', 2), (&'m', 1)], b.most_common(4)[2..].to_vec());
+    assert_eq!(4, b.most_common(10).len());
+    assert!(b.most_common(0).is_empty());
+}
+
+#[test]
+fn bag_algebra() {
+    let a : Bag<i32> = vec![1, 1, 1, 2, 3, 3].into_iter().collect();
+    let b : Bag<i32> = vec![1, 2, 2, 4].into_iter().collect();
+
+    let u = a.union(&b);
+    assert_eq!(vec![1, 1, 1, 2, 2, 3, 3, 4].into_iter().collect::<Bag<_>>(), u);
+    assert_eq!(8, u.total_len());
+
+    let i = a.intersection(&b);
+    assert_eq!(vec![1, 2].into_iter().collect::<Bag<_>>(), i);
+    assert!(a.intersection(&Bag::new()).is_empty());
+    assert_eq!(a, a.union(&Bag::new()));
+}
@@ -0,0 +1,342 @@
+use refs::*;
+use std::iter::{Iterator, FromIterator};
+use std::mem::replace;
+
+use std::cmp::Ord;
+
+use list::List;
+use stats::{MemoryStats, NodeVisitor};
+use show::Disp;
+use std::fmt;
+
+/// A persistent skew binomial heap (Okasaki, "Purely Functional Data
+/// Structures", section 9.3). `insert` is O(1) in the worst case, `pop_min`
+/// and `merge` are O(log n). The public API mirrors `heap::Heap`.
+#[derive(Clone)]
+pub struct Heap<T: Ord+Clone> {
+    // trees in increasing order of rank, only the first two may share a rank
+    trees : List<Ref<Tree<T>>>,
+}
+
+#[derive(Clone,Debug)]
+struct Tree<T: Ord+Clone> {
+    rank     : i32,
+    root     : T,
+    // the extra elements that skew links store next to the root
+    elts     : List<T>,
+    // children in decreasing order of rank
+    children : List<Ref<Tree<T>>>,
+}
+
+impl <T: Ord+Clone> Tree<T> {
+    fn leaf(x: T) -> Tree<T> {
+        Tree { rank: 0, root: x, elts: List::new(), children: List::new() }
+    }
+
+    fn size(&self) -> usize {
+        1 + self.elts.len() as usize + self.children.iter().map(|t| t.size()).sum::<usize>()
+    }
+
+    fn link(t1: Ref<Tree<T>>, t2: Ref<Tree<T>>) -> Tree<T> {
+        let (mut t, child) = if t1.root <= t2.root {
+            (t1.consume(), t2)
+        } else {
+            (t2.consume(), t1)
+        };
+        t.rank += 1;
+        t.children.prepend(child);
+        t
+    }
+
+    fn skew_link(x: T, t1: Ref<Tree<T>>, t2: Ref<Tree<T>>) -> Tree<T> {
+        let mut t = Tree::link(t1, t2);
+        if x <= t.root {
+            let y = replace(&mut t.root, x);
+            t.elts.prepend(y);
+        } else {
+            t.elts.prepend(x);
+        }
+        t
+    }
+}
+
+fn visit_tree<T: Ord+Clone>(t: &Ref<Tree<T>>, v: &mut NodeVisitor) {
+    if v.node_ref(t, 0) {
+        t.elts.visit_nodes(v);
+        t.children.visit_nodes_with(v, visit_tree);
+    }
+}
+
+fn forest_from_vec<T: Ord+Clone>(ts: Vec<Ref<Tree<T>>>) -> List<Ref<Tree<T>>> {
+    let mut ret = List::new();
+    for t in ts.into_iter().rev() {
+        ret.prepend(t);
+    }
+    ret
+}
+
+fn ins_tree<T: Ord+Clone>(t: Ref<Tree<T>>, ts: Vec<Ref<Tree<T>>>) -> Vec<Ref<Tree<T>>> {
+    let mut t = t;
+    let mut ret = Vec::with_capacity(ts.len() + 1);
+    let mut rest = ts.into_iter();
+    loop {
+        match rest.next() {
+            None => {
+                ret.push(t);
+                break
+            }
+            Some(t2) => {
+                if t.rank < t2.rank {
+                    ret.push(t);
+                    ret.push(t2);
+                    ret.extend(rest);
+                    break
+                }
+                t = Ref::new(Tree::link(t, t2));
+            }
+        }
+    }
+    ret
+}
+
+/// Removes the rank duplicate that `insert` may leave at the front.
+fn normalize<T: Ord+Clone>(ts: Vec<Ref<Tree<T>>>) -> Vec<Ref<Tree<T>>> {
+    let mut ts = ts.into_iter();
+    if let Some(t) = ts.next() {
+        ins_tree(t, ts.collect())
+    } else {
+        vec![]
+    }
+}
+
+/// Merges two forests with strictly increasing ranks, like adding two binary
+/// numbers: trees of equal rank are linked and carried to the next rank.
+fn merge_trees<T: Ord+Clone>(ts1: Vec<Ref<Tree<T>>>, ts2: Vec<Ref<Tree<T>>>) -> Vec<Ref<Tree<T>>> {
+    let mut ret = Vec::with_capacity(ts1.len() + ts2.len());
+    let mut ts1 = ts1.into_iter().peekable();
+    let mut ts2 = ts2.into_iter().peekable();
+    let mut carry : Option<Ref<Tree<T>>> = None;
+    loop {
+        let ranks = [ts1.peek().map(|t| t.rank),
+                     ts2.peek().map(|t| t.rank),
+                     carry.as_ref().map(|t| t.rank)];
+        let rank = match ranks.iter().filter_map(|r| *r).min() {
+            Some(rank) => rank,
+            None       => break
+        };
+        let mut same_rank = Vec::with_capacity(3);
+        if ranks[0] == Some(rank) { same_rank.push(ts1.next().unwrap()) }
+        if ranks[1] == Some(rank) { same_rank.push(ts2.next().unwrap()) }
+        if ranks[2] == Some(rank) { same_rank.push(carry.take().unwrap()) }
+
+        if same_rank.len() % 2 == 1 {
+            ret.push(same_rank.pop().unwrap());
+        }
+        if same_rank.len() == 2 {
+            let t2 = same_rank.pop().unwrap();
+            let t1 = same_rank.pop().unwrap();
+            carry = Some(Ref::new(Tree::link(t1, t2)));
+        }
+    }
+    ret
+}
+
+impl <T: Ord+Clone> Heap<T> {
+    pub fn new() -> Heap<T> {
+        Heap { trees: List::new() }
+    }
+
+    /// Builds a heap in O(n), as every insertion is O(1).
+    pub fn from_vec(xs: Vec<T>) -> Heap<T> {
+        let mut ret = Heap::new();
+        for x in xs {
+            ret.insert(x);
+        }
+        ret
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.len() == 0
+    }
+
+    pub fn insert(&mut self, x: T) {
+        if self.trees.len() >= 2 {
+            let t1 = self.trees.pop_front().unwrap();
+            let t2 = self.trees.pop_front().unwrap();
+            if t1.rank == t2.rank {
+                self.trees.prepend(Ref::new(Tree::skew_link(x, t1, t2)));
+                return
+            }
+            self.trees.prepend(t2);
+            self.trees.prepend(t1);
+        }
+        self.trees.prepend(Ref::new(Tree::leaf(x)));
+    }
+
+    pub fn merge(&mut self, other: Heap<T>) {
+        let ts1 = normalize(self.trees.iter().collect());
+        let ts2 = normalize(other.trees.to_iter().collect());
+        self.trees = forest_from_vec(merge_trees(ts1, ts2));
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None
+        }
+        let mut ts : Vec<_> = self.trees.iter().collect();
+        let mut min_idx = 0;
+        for i in 1..ts.len() {
+            if ts[i].root < ts[min_idx].root {
+                min_idx = i;
+            }
+        }
+        let Tree { root, elts, children, .. } = ts.remove(min_idx).consume();
+
+        let mut children : Vec<_> = children.to_iter().collect();
+        children.reverse();
+        self.trees = forest_from_vec(merge_trees(children, normalize(ts)));
+        for x in elts.to_iter() {
+            self.insert(x);
+        }
+        Some(root)
+    }
+
+    pub fn to_iter(self) -> Iter<T> {
+        Iter { heap: self }
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        self.clone().to_iter()
+    }
+
+}
+
+pub struct Iter<T: Ord+Clone> {
+    heap: Heap<T>,
+}
+
+impl <T: Ord+Clone> Iterator for Iter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop_min()
+    }
+}
+
+/// `Heap{min: 1, len: 5}`, or with `{:#?}` the trees.
+impl <T: Ord+Clone+fmt::Debug> fmt::Debug for Heap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Heap").field("trees", &self.trees).finish()
+        }
+        let mut min = None;
+        let mut len = 0;
+        for t in self.trees.iter() {
+            len += t.size();
+            if min.as_ref().map_or(true, |m| t.root < *m) {
+                min = Some(t.root.clone());
+            }
+        }
+        match min {
+            Some(min) => write!(f, "Heap{{min: {:?}, len: {}}}", min, len),
+            None      => write!(f, "Heap{{len: 0}}")
+        }
+    }
+}
+
+/// The elements in increasing order, `Heap[1, 2, 3]`.
+impl <T: Ord+Clone+fmt::Display> fmt::Display for Heap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Heap")?;
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+impl <T: Ord+Clone> MemoryStats for Heap<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.trees.visit_nodes_with(v, visit_tree)
+    }
+}
+
+impl <T: Ord+Clone> FromIterator<T> for Heap<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Heap<T> {
+        let mut ret = Heap::new();
+        for x in iter {
+            ret.insert(x);
+        }
+        ret
+    }
+}
+
+
+#[test]
+fn bh_empty() {
+    let mut h : Heap<i8> = Heap::new();
+    assert!(h.is_empty());
+    assert_eq!(None, h.pop_min())
+}
+
+#[test]
+fn bh_min() {
+    let mut h : Ref<Heap<i8>> = Ref::new(Heap::new());
+    h.insert(4);
+    h.insert(2);
+    h.insert(1);
+    h.insert(5);
+    h.insert(3);
+    assert_eq!(Some(1), h.pop_min());
+    assert_eq!(Some(2), h.pop_min());
+    assert_eq!(Some(3), h.pop_min());
+    assert_eq!(Some(4), h.pop_min());
+    assert_eq!(Some(5), h.pop_min());
+    assert_eq!(None,    h.pop_min());
+    assert_eq!(None,    h.pop_min());
+}
+
+#[test]
+fn bh_iter() {
+    let h : Heap<i32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+
+    let mut i = 0;
+    for v in h.iter() {
+        assert_eq!(i, v);
+        i += 1;
+    }
+    assert_eq!(1000, i);
+}
+
+#[test]
+fn bh_merge() {
+    let mut h1 = Heap::from_vec(vec![9, 1, 5, 7, 3]);
+    let h2 = Heap::from_vec(vec![2, 8, 4, 0, 6]);
+    h1.merge(h2);
+
+    let mut i = 0;
+    for v in h1.to_iter() {
+        assert_eq!(i, v);
+        i += 1;
+    }
+    assert_eq!(10, i);
+}
+
+#[test]
+fn bh_persistent() {
+    let mut h1 = Heap::from_vec(vec![3, 1, 2]);
+    let mut h2 = h1.clone();
+    h2.insert(0);
+
+    assert_eq!(Some(1), h1.pop_min());
+    assert_eq!(Some(0), h2.pop_min());
+    assert_eq!(Some(1), h2.pop_min());
+    assert_eq!(Some(2), h1.pop_min());
+    assert_eq!(Some(3), h1.pop_min());
+    assert_eq!(None,    h1.pop_min());
+    assert_eq!(Some(2), h2.pop_min());
+}
+
+#[test]
+fn bh_fmt() {
+    let h = Heap::from_vec(vec![4, 2, 7, 1, 5, 3, 6]);
+    assert_eq!("Heap{min: 1, len: 7}", format!("{:?}", h));
+    assert_eq!("Heap[1, 2, 3, 4, 5, 6, 7]", format!("{}", h));
+    assert_eq!("Heap{len: 0}", format!("{:?}", Heap::<i32>::new()));
+}
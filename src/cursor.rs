@@ -0,0 +1,177 @@
+use std::mem::replace;
+
+/// A sequence built and taken apart at its front, like a stack. This is all
+/// a `Cursor` needs: `List` and `SpineList` implement it.
+pub trait StackSeq: Sized {
+    type Elt;
+
+    fn empty() -> Self;
+
+    fn push_front(&mut self, x: Self::Elt);
+
+    fn pop_front(&mut self) -> Option<Self::Elt>;
+}
+
+/// A zipper over a sequence. The elements before the cursor are kept in
+/// reverse, so that it can move back; the elements after it are shared with
+/// the sequence the cursor was made from. Each move is one push and one pop.
+pub struct Cursor<S: StackSeq> {
+    before : S,
+    cur    : Option<S::Elt>,
+    after  : S,
+    index  : usize,
+}
+
+impl <S: StackSeq> Cursor<S> {
+    /// A cursor on the first element of `seq`.
+    pub fn new(seq: S) -> Cursor<S> {
+        let mut after = seq;
+        let cur = after.pop_front();
+        Cursor { before: S::empty(), cur: cur, after: after, index: 0 }
+    }
+
+    /// The position of the cursor. Past the last element, this is the length
+    /// of the sequence.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The element under the cursor, `None` past the last element.
+    pub fn current(&self) -> Option<&S::Elt> {
+        self.cur.as_ref()
+    }
+
+    /// Returns false if the cursor already is past the last element.
+    pub fn move_next(&mut self) -> bool {
+        if let Some(x) = self.cur.take() {
+            self.before.push_front(x);
+            self.cur = self.after.pop_front();
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns false if the cursor already is on the first element.
+    pub fn move_prev(&mut self) -> bool {
+        if let Some(x) = self.before.pop_front() {
+            if let Some(cur) = self.cur.take() {
+                self.after.push_front(cur);
+            }
+            self.cur = Some(x);
+            self.index -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The elements before the cursor and those from the cursor on.
+    pub fn split(self) -> (S, S) {
+        let Cursor { mut before, cur, mut after, .. } = self;
+        if let Some(cur) = cur {
+            after.push_front(cur);
+        }
+        let mut prefix = S::empty();
+        while let Some(x) = before.pop_front() {
+            prefix.push_front(x);
+        }
+        (prefix, after)
+    }
+
+    fn insert_before(&mut self, x: S::Elt) {
+        self.before.push_front(x);
+        self.index += 1;
+    }
+
+    fn insert_after(&mut self, x: S::Elt) {
+        if self.cur.is_some() {
+            self.after.push_front(x);
+        } else {
+            self.cur = Some(x);
+        }
+    }
+
+    fn remove_current(&mut self) -> Option<S::Elt> {
+        let ret = self.cur.take();
+        self.cur = self.after.pop_front();
+        ret
+    }
+
+    fn into_seq(self) -> S {
+        let Cursor { mut before, cur, mut after, .. } = self;
+        if let Some(cur) = cur {
+            after.push_front(cur);
+        }
+        while let Some(x) = before.pop_front() {
+            after.push_front(x);
+        }
+        after
+    }
+}
+
+/// A `Cursor` that can edit the sequence it was made from. The edits are
+/// written back when it is dropped.
+pub struct CursorMut<'a, S: StackSeq+'a> {
+    seq    : &'a mut S,
+    cursor : Cursor<S>,
+}
+
+impl <'a, S: StackSeq> CursorMut<'a, S> {
+    /// A cursor on the first element of `seq`, which is left empty until the
+    /// cursor is dropped.
+    pub fn new(seq: &'a mut S) -> CursorMut<'a, S> {
+        let taken = replace(seq, S::empty());
+        CursorMut { seq: seq, cursor: Cursor::new(taken) }
+    }
+
+    pub fn index(&self) -> usize {
+        self.cursor.index()
+    }
+
+    pub fn current(&self) -> Option<&S::Elt> {
+        self.cursor.current()
+    }
+
+    pub fn move_next(&mut self) -> bool {
+        self.cursor.move_next()
+    }
+
+    pub fn move_prev(&mut self) -> bool {
+        self.cursor.move_prev()
+    }
+
+    /// Inserts `x` before the cursor, which stays on the same element.
+    pub fn insert_before(&mut self, x: S::Elt) {
+        self.cursor.insert_before(x)
+    }
+
+    /// Inserts `x` after the current element. Past the last element, `x` is
+    /// appended and becomes the current element.
+    pub fn insert_after(&mut self, x: S::Elt) {
+        self.cursor.insert_after(x)
+    }
+
+    /// Removes the current element, the cursor moves on to the next one.
+    pub fn remove_current(&mut self) -> Option<S::Elt> {
+        self.cursor.remove_current()
+    }
+
+    /// Cuts the sequence before the cursor. The sequence keeps the elements
+    /// before the cursor, the rest is returned.
+    pub fn split(mut self) -> S {
+        let mut tail = replace(&mut self.cursor.after, S::empty());
+        if let Some(cur) = self.cursor.cur.take() {
+            tail.push_front(cur);
+        }
+        tail
+    }
+}
+
+impl <'a, S: StackSeq> Drop for CursorMut<'a, S> {
+    fn drop(&mut self) {
+        let cursor = replace(&mut self.cursor, Cursor::new(S::empty()));
+        *self.seq = cursor.into_seq();
+    }
+}
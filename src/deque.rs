@@ -1,57 +1,95 @@
 
-use giftr::refs::*;
-//use giftr::refs::functional::Ref as Ref;
-use giftr::refs::imperative::Ref as Ref;
+use std::fmt;
+use std::fmt::Debug;
 use std::iter::Iterator;
-use std::mem::swap;
+use std::ops::Index;
 
-use list::List;
+use fingertree::IndexedSeq;
+use stats::{MemoryStats, NodeVisitor};
+use dot::{ToDot, Graph};
+use diff::{Diff, Edit, PatchError, script};
+use show::deque_parts;
 
-#[derive(Clone, Debug)]
+/// A persistent double-ended queue on a finger tree: `append` and
+/// `split_off` take O(log n). `reverse` only turns the top of the tree
+/// around, so it is O(1), and the other operations stay O(log n) on reversed
+/// deques, whichever way round the two sides of an `append` are.
+#[derive(Clone)]
 pub struct Deque<T: Clone> {
-    front : Ref<List<T>>,
-    back  : Ref<List<T>>,
+    seq : IndexedSeq<T>,
 }
 
 impl <T: Clone> Deque<T> {
     pub fn new() -> Deque<T> {
-        Deque { front: Ref::new(List::new()), back: Ref::new(List::new())}
+        Deque { seq: IndexedSeq::new() }
     }
 
     pub fn push_front(&mut self, x: T) {
-        self.front.prepend(x);
+        self.seq.push_front(x)
     }
 
     pub fn push_back(&mut self, x: T) {
-        self.back.prepend(x);
+        self.seq.push_back(x)
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        let front = self.front.pop_front();
-        if front.is_some() {
-            front
-        } else {
-            // FIXME we should reshuffle here, right?
-            self.back.pop_back()
-        }
+        self.seq.pop_front()
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
-        let back = self.back.pop_front();
-        if back.is_some() {
-            back
-        } else {
-            // FIXME we should reshuffle here, right?
-            self.front.pop_back()
+        self.seq.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.seq.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seq.is_empty()
+    }
+
+    /// The element `pop_front` would return, without removing it.
+    pub fn front(&self) -> Option<&T> {
+        self.seq.front()
+    }
+
+    /// The element `pop_back` would return, without removing it.
+    pub fn back(&self) -> Option<&T> {
+        self.seq.back()
+    }
+
+    /// The `idx`th element from the front, in O(log n).
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.seq.get(idx)
+    }
+
+    /// Moves all elements of `other` to the back of `self`, in O(log n).
+    pub fn append(&mut self, other: Deque<T>) {
+        self.seq.append(other.seq)
+    }
+
+    /// Concatenates the deques, in order.
+    pub fn concat(deques: Vec<Deque<T>>) -> Deque<T> {
+        let mut ret = Deque::new();
+        for d in deques {
+            ret.append(d);
         }
+        ret
+    }
+
+    /// Keeps the first `at` elements and returns a deque holding the rest.
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> Deque<T> {
+        assert!(at <= self.len(), "deque: split index out of bounds");
+        Deque { seq: self.seq.split_off(at) }
     }
 
     pub fn reverse(&mut self) {
-        swap(&mut self.front, &mut self.back);
+        self.seq.reverse()
     }
 
     fn to_iter(self) -> Iter<T> {
-        Iter { deque: Ref::new(self) }
+        Iter { deque: self }
     }
 
     pub fn iter(&self) -> Iter<T> {
@@ -65,8 +103,96 @@ impl <T: Clone> Deque<T> {
     }
 }
 
+impl <T: Clone> Index<usize> for Deque<T> {
+    type Output = T;
+    fn index(&self, idx: usize) -> &T {
+        self.get(idx).expect("deque index out of bounds")
+    }
+}
+
+impl <T: Clone> MemoryStats for Deque<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.seq.visit_nodes(v)
+    }
+}
+
+// The common ends are found on the trees, skipping the subtrees the two
+// versions share; only the elements between them are compared.
+impl <T: Clone+PartialEq> Diff for Deque<T> {
+    type Elt = T;
+
+    fn diff(&self, other: &Deque<T>) -> Vec<Edit<T>> {
+        let (n, m) = (self.len(), other.len());
+        let (prefix, suffix) = (self.seq.common_prefix_len(&other.seq), self.seq.common_suffix_len(&other.seq));
+        let suffix = suffix.min(n.min(m) - prefix);
+        let old : Vec<&T> = (prefix..n - suffix).map(|i| &self[i]).collect();
+        let new : Vec<&T> = (prefix..m - suffix).map(|i| &other[i]).collect();
+        script(&old, &new, prefix)
+    }
+
+    fn patch(&mut self, edits: &[Edit<T>]) -> Result<(), PatchError> {
+        let mut ret = self.clone();
+        for (i, edit) in edits.iter().enumerate() {
+            let (at, x, remove) = match *edit {
+                Edit::Insert(at, ref x)  => (at, Some(x), false),
+                Edit::Delete(at)         => (at, None, true),
+                Edit::Replace(at, ref x) => (at, Some(x), true)
+            };
+            if at > ret.len() || (remove && at == ret.len()) {
+                return Err(PatchError { edit: i })
+            }
+            let mut tail = ret.split_off(at);
+            if remove {
+                tail.pop_front();
+            }
+            if let Some(x) = x {
+                ret.push_back(x.clone());
+            }
+            ret.append(tail);
+        }
+        *self = ret;
+        Ok(())
+    }
+}
+
+impl <T: Clone> Deque<T> {
+    // Writes `Deque[1, 2 | 3, 4]`, with the front half of the elements
+    // before the bar and the back half after it. The tree has no front and
+    // back parts of its own; `{:#?}` shows how it holds them.
+    fn fmt_with<F: Fn(&T, &mut fmt::Formatter) -> fmt::Result>(&self, f: &mut fmt::Formatter, each: F) -> fmt::Result {
+        let xs : Vec<&T> = self.seq.elts().collect();
+        let (front, back) = xs.split_at((xs.len() + 1) / 2);
+        deque_parts(f, front, back, each)
+    }
+}
+
+/// `Deque[1, 2 | 3, 4]`, or with `{:#?}` the tree with its fingers.
+impl <T: Clone+Debug> Debug for Deque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Deque").field("seq", &self.seq).finish()
+        }
+        self.fmt_with(f, |x, f| Debug::fmt(x, f))
+    }
+}
+
+impl <T: Clone+fmt::Display> fmt::Display for Deque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(f, |x, f| fmt::Display::fmt(x, f))
+    }
+}
+
+impl <T: Clone+Debug> ToDot for Deque<T> {
+    fn to_dot(&self, g: &mut Graph) -> String {
+        let id = g.record(&["Deque".to_string()]);
+        let seq = self.seq.to_dot(g);
+        g.edge(&id, &seq, "");
+        id
+    }
+}
+
 pub struct Iter<T: Clone> {
-    deque : Ref<Deque<T>>,
+    deque : Deque<T>,
 }
 
 impl <T: Clone> Iterator for Iter<T> {
@@ -77,6 +203,54 @@ impl <T: Clone> Iterator for Iter<T> {
 }
 
 
+#[test]
+fn deque_fmt() {
+    let mut d = Deque::new();
+    d.push_back("b");
+    d.push_front("a");
+    assert_eq!("Deque[\"a\" | \"b\"]", format!("{:?}", d));
+    assert_eq!("Deque[a | b]", format!("{}", d));
+    let mut d : Deque<i32> = Deque::new();
+    assert_eq!("Deque[]", format!("{:?}", d));
+    d.push_back(1);
+    assert_eq!("Deque[1]", format!("{:?}", d));
+    for i in 2..10 {
+        d.push_back(i);
+    }
+    assert_eq!("Deque[1, 2, 3, 4, 5 | 6, 7, 8, 9]", format!("{:?}", d));
+    d.reverse();
+    assert_eq!("Deque[9, 8, 7, 6, 5 | 4, 3, 2, 1]", format!("{}", d));
+    assert!(format!("{:#?}", d).starts_with("Deque {\n    seq: FingerTree {"));
+}
+
+#[test]
+fn deque_diff() {
+    let mut base = Deque::new();
+    for i in 0..1000 {
+        base.push_back(i);
+    }
+    let mut ours = base.clone();
+    ours.push_front(7);
+    ours.pop_back();
+    let mut tail = ours.split_off(500);
+    tail.pop_front();
+    ours.push_back(1);
+    ours.append(tail);
+
+    let edits = base.diff(&ours);
+    assert_eq!(vec![Edit::Insert(0, 7), Edit::Replace(500, 1), Edit::Delete(1000)], edits);
+    let mut patched = base.clone();
+    patched.patch(&edits).unwrap();
+    assert_eq!(ours.iter().collect::<Vec<_>>(), patched.iter().collect::<Vec<_>>());
+    assert_eq!(ours.rev_iter().collect::<Vec<_>>(), patched.rev_iter().collect::<Vec<_>>());
+    assert!(base.diff(&base.clone()).is_empty());
+
+    let mut short = base.clone();
+    short.split_off(3);
+    assert_eq!(Err(PatchError { edit: 1 }), short.patch(&[Edit::Delete(0), Edit::Delete(2)]));
+    assert_eq!(vec![0, 1, 2], short.iter().collect::<Vec<_>>());
+}
+
 #[test]
 fn deque_pop_front() {
     let mut d = Deque::new();
@@ -122,6 +296,7 @@ fn deque_reverse() {
 
 #[test]
 fn deque_iter() {
+    use refs::Ref;
     let mut d = Ref::new(Deque::new());
     d.push_front(2);
     d.push_front(1);
@@ -140,3 +315,213 @@ fn deque_iter() {
 //        i -= 1;
 //    }
 }
+
+#[test]
+fn deque_len() {
+    let mut d = Deque::new();
+    assert_eq!(0, d.len());
+    assert!(d.is_empty());
+    d.push_front(2);
+    d.push_front(1);
+    d.push_back(3);
+    assert_eq!(3, d.len());
+    assert!(!d.is_empty());
+
+    d.pop_back();
+    d.pop_back();
+    d.pop_back();
+    d.pop_back();
+    assert_eq!(0, d.len());
+    assert!(d.is_empty());
+}
+
+#[test]
+fn deque_front_back() {
+    let mut d = Deque::new();
+    assert_eq!(None, d.front());
+    assert_eq!(None, d.back());
+
+    d.push_back(1);
+    assert_eq!(Some(&1), d.front());
+    assert_eq!(Some(&1), d.back());
+
+    d.push_back(2);
+    d.push_front(0);
+    assert_eq!(Some(&0), d.front());
+    assert_eq!(Some(&2), d.back());
+    assert_eq!(3, d.len());
+}
+
+#[test]
+fn deque_get() {
+    let mut d = Deque::new();
+    d.push_front(2);
+    d.push_front(1);
+    d.push_back(3);
+    d.push_back(4);
+
+    assert_eq!(Some(&1), d.get(0));
+    assert_eq!(Some(&2), d.get(1));
+    assert_eq!(Some(&3), d.get(2));
+    assert_eq!(Some(&4), d.get(3));
+    assert_eq!(None,     d.get(4));
+    assert_eq!(3, d[2]);
+
+    d.reverse();
+    assert_eq!(4, d[0]);
+    assert_eq!(1, d[3]);
+}
+
+#[test]
+fn deque_append() {
+    let mut d1 = Deque::new();
+    d1.push_front(2);
+    d1.push_front(1);
+    let mut d2 = Deque::new();
+    d2.push_back(3);
+    d2.push_back(4);
+
+    d1.append(d2);
+    assert_eq!(4, d1.len());
+    assert_eq!(Some(4), d1.pop_back());
+    assert_eq!(Some(1), d1.pop_front());
+
+    d1.reverse();
+    assert_eq!(Some(3), d1.pop_front());
+    assert_eq!(Some(2), d1.pop_front());
+    assert_eq!(None, d1.pop_front());
+}
+
+#[test]
+fn deque_split_off() {
+    let mut d = Deque::new();
+    for i in 0..100 {
+        d.push_back(i);
+    }
+    let mut tail = d.split_off(40);
+    assert_eq!(40, d.len());
+    assert_eq!(60, tail.len());
+    assert_eq!(Some(&39), d.back());
+    assert_eq!(Some(&40), tail.front());
+
+    tail.reverse();
+    let mut i = 99;
+    for v in tail.iter() {
+        assert_eq!(i, v);
+        i -= 1;
+    }
+    assert_eq!(39, i);
+
+    d.reverse();
+    assert_eq!(Some(39), d.pop_front());
+    assert_eq!(Some(0), d.pop_back());
+}
+
+#[test]
+fn deque_reversed() {
+    let mut d = Deque::new();
+    for i in 0..10 {
+        d.push_back(i);
+    }
+    d.reverse();
+    assert_eq!(Some(&9), d.get(0));
+    assert_eq!(None, d.get(10));
+    let mut tail = d.split_off(3);
+    assert_eq!(vec![9, 8, 7], d.iter().collect::<Vec<_>>());
+    assert_eq!(vec![6, 5, 4, 3, 2, 1, 0], tail.iter().collect::<Vec<_>>());
+
+    // both reversed, then only one of them
+    tail.append(d.clone());
+    assert_eq!(vec![6, 5, 4, 3, 2, 1, 0, 9, 8, 7], tail.iter().collect::<Vec<_>>());
+    let mut e = Deque::new();
+    e.push_back(10);
+    e.append(tail.clone());
+    tail.append(e);
+    assert_eq!(21, tail.len());
+    assert_eq!(Some(&6), tail.front());
+    assert_eq!(Some(&7), tail.back());
+    assert_eq!(Some(&10), tail.get(10));
+
+    let mut rev = tail.clone();
+    rev.reverse();
+    assert!(rev.diff(&rev.clone()).is_empty());
+    let mut other = tail.clone();
+    other.push_front(-1);
+    assert_eq!(vec![Edit::Insert(0, -1)], tail.diff(&other));
+}
+
+#[test]
+fn deque_append_reversed() {
+    use stats::total_memory;
+    let mut fwd = Deque::new();
+    let mut rev = Deque::new();
+    for i in 0..10000 {
+        fwd.push_back(i);
+        rev.push_front(10000 + i);
+    }
+    rev.reverse();
+    let mut d = fwd.clone();
+    d.append(rev.clone());
+    assert_eq!(20000, d.len());
+    assert!(d.iter().eq(0..20000));
+    assert!(d.rev_iter().eq((0..20000).rev()));
+    assert_eq!(Some(&10000), d.get(10000));
+    // appending made a few nodes and shares the rest
+    let apart = total_memory(&[fwd.clone(), rev.clone()]);
+    assert!(total_memory(&[fwd.clone(), rev.clone(), d.clone()]) - apart < apart / 20);
+
+    // and the other way round, then split across the seam
+    let mut d = rev.clone();
+    d.append(fwd.clone());
+    let tail = d.split_off(15000);
+    assert!(d.iter().eq((10000..20000).chain(0..5000)));
+    assert!(tail.iter().eq(5000..10000));
+    let mut both = tail.clone();
+    both.reverse();
+    both.append(tail);
+    assert!(both.iter().eq((5000..10000).rev().chain(5000..10000)));
+}
+
+#[test]
+fn deque_concat() {
+    let deques = (0..10).map(|i| {
+        let mut d = Deque::new();
+        for j in 0..i {
+            d.push_back(i * 10 + j);
+        }
+        d
+    }).collect();
+    let mut d = Deque::concat(deques);
+    assert_eq!(45, d.len());
+    let mut prev = -1;
+    while let Some(v) = d.pop_front() {
+        assert!(prev < v);
+        prev = v;
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn deque_threads() {
+    use std::thread;
+    let mut d = Deque::new();
+    for i in 0..1000 {
+        d.push_back(i);
+    }
+    let workers : Vec<_> = (0..4).map(|t| {
+        let mut mine = d.clone();
+        thread::spawn(move || {
+            mine.push_front(-t);
+            mine.pop_back();
+            let tail = mine.split_off(500);
+            (mine.len(), mine.front().cloned(), tail.back().cloned())
+        })
+    }).collect();
+    for (t, worker) in workers.into_iter().enumerate() {
+        assert_eq!((500, Some(-(t as i32)), Some(998)), worker.join().unwrap());
+    }
+    assert_eq!(1000, d.len());
+    assert_eq!(Some(&999), d.back());
+    let iter = d.iter();
+    assert_eq!(999 * 1000 / 2, thread::spawn(move || iter.sum::<i32>()).join().unwrap());
+}
@@ -0,0 +1,255 @@
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+
+use cursor::{CursorMut, StackSeq};
+
+/// One step of an edit script. Positions are those of the sequence as left
+/// by the edits before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit<T> {
+    Insert(usize, T),
+    Delete(usize),
+    Replace(usize, T),
+}
+
+/// Sequences that can be compared into an edit script, and patched with one.
+/// `List`, `SpineList` and `Deque` support it.
+pub trait Diff {
+    type Elt;
+
+    /// A shortest script turning `self` into `other`. The parts that the two
+    /// versions share are skipped without comparing their elements, where
+    /// the structure allows it.
+    fn diff(&self, other: &Self) -> Vec<Edit<Self::Elt>>;
+
+    /// Applies `edits` in order. If one of them does not fit, `self` is left
+    /// as it was.
+    fn patch(&mut self, edits: &[Edit<Self::Elt>]) -> Result<(), PatchError>;
+}
+
+/// An edit whose position lies past the end of the sequence it is applied
+/// to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchError {
+    /// The index of the edit in the script.
+    pub edit : usize,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "edit {} is out of bounds", self.edit)
+    }
+}
+
+impl Error for PatchError {}
+
+enum Op {
+    Keep,
+    Delete,
+    // the index of the element of `new` inserted
+    Insert(usize),
+}
+
+fn same<T: PartialEq>(x: &T, y: &T) -> bool {
+    ptr::eq(x, y) || x == y
+}
+
+// Myers' O((n + m) d) algorithm, for a difference of d elements.
+fn shortest_ops<T: PartialEq>(old: &[&T], new: &[&T]) -> Vec<Op> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let off = max;
+    let at = |k: isize| (off + k) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // the furthest points reached on diagonals -d to d, before each round d;
+    // the backtracking below looks no further
+    let mut trace = vec![];
+    let mut end = 0;
+    'search: for d in 0..max + 1 {
+        trace.push(v[at(-d)..at(d) + 1].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && same(old[x as usize], new[y as usize]) {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                end = d;
+                break 'search
+            }
+            k += 2;
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut x, mut y) = (n, m);
+    for d in (0..end + 1).rev() {
+        let (prev_x, prev_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let w = &trace[d as usize];
+            let v = |k: isize| w[(k + d) as usize];
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && v(k - 1) < v(k + 1)) { k + 1 } else { k - 1 };
+            (v(prev_k), v(prev_k) - prev_k)
+        };
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push(Op::Insert(y as usize - 1));
+            } else {
+                ops.push(Op::Delete);
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Applies `edits` to `seq` in order, walking a cursor from one edit to the
+/// next. If one of them does not fit, `seq` is left as it was.
+pub fn patch_seq<S: StackSeq+Clone>(seq: &mut S, edits: &[Edit<S::Elt>]) -> Result<(), PatchError>
+    where S::Elt: Clone
+{
+    let mut ret = seq.clone();
+    {
+        let mut c = CursorMut::new(&mut ret);
+        for (i, edit) in edits.iter().enumerate() {
+            let at = match *edit {
+                Edit::Insert(at, _) | Edit::Delete(at) | Edit::Replace(at, _) => at
+            };
+            while c.index() > at {
+                c.move_prev();
+            }
+            while c.index() < at && c.move_next() {}
+            if c.index() != at {
+                return Err(PatchError { edit: i })
+            }
+            match *edit {
+                Edit::Insert(_, ref x) => c.insert_before(x.clone()),
+                Edit::Delete(_) => {
+                    if c.remove_current().is_none() {
+                        return Err(PatchError { edit: i })
+                    }
+                }
+                Edit::Replace(_, ref x) => {
+                    if c.remove_current().is_none() {
+                        return Err(PatchError { edit: i })
+                    }
+                    c.insert_before(x.clone());
+                }
+            }
+        }
+    }
+    *seq = ret;
+    Ok(())
+}
+
+/// The script turning `old` into `new`, for sequences that have the same
+/// first `at` elements; the positions of the edits are offset by `at`. A
+/// deletion next to an insertion makes a replacement.
+pub fn script<T: PartialEq+Clone>(old: &[&T], new: &[&T], at: usize) -> Vec<Edit<T>> {
+    let mut ret = vec![];
+    let mut pos = at;
+    let mut ops = shortest_ops(old, new).into_iter().peekable();
+    while let Some(op) = ops.next() {
+        match op {
+            Op::Keep => pos += 1,
+            Op::Delete => {
+                if let Some(&Op::Insert(j)) = ops.peek() {
+                    ops.next();
+                    ret.push(Edit::Replace(pos, new[j].clone()));
+                    pos += 1;
+                } else {
+                    ret.push(Edit::Delete(pos));
+                }
+            }
+            Op::Insert(j) => {
+                if let Some(&Op::Delete) = ops.peek() {
+                    ops.next();
+                    ret.push(Edit::Replace(pos, new[j].clone()));
+                } else {
+                    ret.push(Edit::Insert(pos, new[j].clone()));
+                }
+                pos += 1;
+            }
+        }
+    }
+    ret
+}
+
+/// An element that panics when it is compared, for tests that check that
+/// the parts two versions share are skipped without looking at them.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct NoEq;
+
+#[cfg(test)]
+impl PartialEq for NoEq {
+    fn eq(&self, _: &NoEq) -> bool {
+        panic!("compared elements of a shared part")
+    }
+}
+
+#[test]
+fn diff_script() {
+    let a = vec!['a', 'b', 'c', 'a', 'b', 'b', 'a'];
+    let b = vec!['c', 'b', 'a', 'b', 'a', 'c'];
+    let (ra, rb) : (Vec<_>, Vec<_>) = (a.iter().collect(), b.iter().collect());
+    let edits = script(&ra, &rb, 0);
+    // the distance between the two is 5, counting a replacement as two
+    let cost : usize = edits.iter().map(|e| match *e { Edit::Replace(..) => 2, _ => 1 }).sum();
+    assert_eq!(5, cost);
+
+    let mut patched = a.clone();
+    for e in edits {
+        match e {
+            Edit::Insert(i, x)  => patched.insert(i, x),
+            Edit::Delete(i)     => { patched.remove(i); }
+            Edit::Replace(i, x) => patched[i] = x
+        }
+    }
+    assert_eq!(b, patched);
+
+    assert_eq!(vec![Edit::Replace(3, 'x')], script(&[&'a'], &[&'x'], 3));
+    assert!(script::<i32>(&[], &[], 0).is_empty());
+    assert_eq!(vec![Edit::Insert(0, 1), Edit::Insert(1, 2)], script(&[], &[&1, &2], 0));
+    assert_eq!(vec![Edit::Delete(0), Edit::Delete(0)], script(&[&1, &2], &[], 0));
+
+    // scattered changes in a long sequence
+    let a : Vec<u32> = (0..2000).collect();
+    let mut b = a.clone();
+    for i in (0..40).rev() {
+        if i % 2 == 0 {
+            b.remove(i * 47 + 3);
+        } else {
+            b.insert(i * 47, 5000 + i as u32);
+        }
+    }
+    let (ra, rb) : (Vec<_>, Vec<_>) = (a.iter().collect(), b.iter().collect());
+    let edits = script(&ra, &rb, 0);
+    assert_eq!(40, edits.len());
+    let mut patched = a.clone();
+    for e in edits {
+        match e {
+            Edit::Insert(i, x)  => patched.insert(i, x),
+            Edit::Delete(i)     => { patched.remove(i); }
+            Edit::Replace(i, x) => patched[i] = x
+        }
+    }
+    assert_eq!(b, patched);
+}
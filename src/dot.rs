@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use refs::*;
+
+/// Collections that can be drawn by `Graph`: `List`, `SpineList`, `Deque`,
+/// `FingerTree` and `Heap`. `SpineList` keeps its spine inside giftr, out of
+/// reach, so its elements are drawn as a chain of records that no two values
+/// share.
+pub trait ToDot {
+    /// Adds the nodes of `self` to `g` and returns the id of the first one.
+    fn to_dot(&self, g: &mut Graph) -> String;
+}
+
+/// A Graphviz graph of one or more collection values. Nodes behind a `Ref`
+/// are drawn once however many values reach them, labelled with their
+/// strong count, and filled grey when that count is above one.
+pub struct Graph {
+    // node addresses and their ids
+    ids   : HashMap<usize, usize>,
+    next  : usize,
+    lines : Vec<String>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph { ids: HashMap::new(), next: 0, lines: vec![] }
+    }
+
+    /// Draws `value` under `name`.
+    pub fn add<C: ToDot>(&mut self, name: &str, value: &C) {
+        let name = format!("\"{}\"", quote(name));
+        self.lines.push(format!("{} [shape=plaintext];", name));
+        let root = value.to_dot(self);
+        self.edge(&name, &root, "");
+    }
+
+    /// The graph in the DOT language.
+    pub fn render(&self) -> String {
+        let mut ret = String::from("digraph {\n    node [shape=record];\n");
+        for line in &self.lines {
+            ret.push_str("    ");
+            ret.push_str(line);
+            ret.push('\n');
+        }
+        ret.push_str("}\n");
+        ret
+    }
+
+    /// A node with the given fields that is not shared, such as a value
+    /// held inline by a collection.
+    pub fn record(&mut self, fields: &[String]) -> String {
+        let id = format!("n{}", self.next);
+        self.next += 1;
+        self.lines.push(format!("{} [label=\"{}\"];", id, label(fields)));
+        id
+    }
+
+    /// The node behind `r`, with the given fields followed by its strong
+    /// count. Returns its id and whether it was new, in which case the
+    /// caller adds what it points to.
+    pub fn node_ref<X: Clone>(&mut self, r: &Ref<X>, fields: &[String]) -> (String, bool) {
+        let addr = &*r._ptr as *const X as usize;
+        if let Some(&id) = self.ids.get(&addr) {
+            return (format!("n{}", id), false)
+        }
+        let count = Rc::strong_count(&r._ptr);
+        let mut fields = fields.to_vec();
+        fields.push(format!("rc {}", count));
+        let id = self.next;
+        self.next += 1;
+        self.ids.insert(addr, id);
+        let style = if count > 1 { ", style=filled, fillcolor=lightgrey" } else { "" };
+        self.lines.push(format!("n{} [label=\"{}\"{}];", id, label(&fields), style));
+        (format!("n{}", id), true)
+    }
+
+    pub fn edge(&mut self, from: &str, to: &str, label: &str) {
+        if label.is_empty() {
+            self.lines.push(format!("{} -> {};", from, to));
+        } else {
+            self.lines.push(format!("{} -> {} [label=\"{}\"];", from, to, quote(label)));
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// The fields of a record label, with the characters that delimit fields
+// escaped.
+fn label(fields: &[String]) -> String {
+    let fields : Vec<String> = fields.iter().map(|field| {
+        let mut ret = String::new();
+        for c in field.chars() {
+            match c {
+                '\\' | '"' | '{' | '}' | '|' | '<' | '>' => {
+                    ret.push('\\');
+                    ret.push(c);
+                }
+                '\n' => ret.push_str("\\n"),
+                _    => ret.push(c)
+            }
+        }
+        ret
+    }).collect();
+    fields.join("|")
+}
+
+
+#[test]
+fn dot_list() {
+    use list::List;
+    let mut base = List::new();
+    base.prepend(1);
+    base.prepend(2);
+    let mut v = base.clone();
+    v.prepend(3);
+
+    let mut g = Graph::new();
+    g.add("base", &base);
+    g.add("v", &v);
+    g.add("empty", &List::<i32>::new());
+    assert_eq!(
+"digraph {
+    node [shape=record];
+    \"base\" [shape=plaintext];
+    n0 [label=\"2|rc 2\", style=filled, fillcolor=lightgrey];
+    n1 [label=\"1|rc 1\"];
+    n0 -> n1;
+    \"base\" -> n0;
+    \"v\" [shape=plaintext];
+    n2 [label=\"3|rc 1\"];
+    n2 -> n0;
+    \"v\" -> n2;
+    \"empty\" [shape=plaintext];
+    n3 [label=\"nil\"];
+    \"empty\" -> n3;
+}
+", g.render());
+}
+
+#[test]
+fn dot_heap() {
+    use heap::Heap;
+    let mut h = Heap::new();
+    h.insert(1);
+    let mut h2 = h.clone();
+    h2.insert(2);
+
+    let mut g = Graph::new();
+    g.add("h", &h);
+    g.add("h2", &h2);
+    assert_eq!(
+"digraph {
+    node [shape=record];
+    \"h\" [shape=plaintext];
+    n0 [label=\"1|rank 1|rc 1\"];
+    n1 [label=\"empty|rc 7\", style=filled, fillcolor=lightgrey];
+    n0 -> n1 [label=\"l\"];
+    n0 -> n1 [label=\"r\"];
+    \"h\" -> n0;
+    \"h2\" [shape=plaintext];
+    n2 [label=\"1|rank 1|rc 1\"];
+    n3 [label=\"2|rank 1|rc 1\"];
+    n3 -> n1 [label=\"l\"];
+    n3 -> n1 [label=\"r\"];
+    n2 -> n3 [label=\"l\"];
+    n2 -> n1 [label=\"r\"];
+    \"h2\" -> n2;
+}
+", g.render());
+}
+
+#[test]
+fn dot_deque() {
+    use deque::Deque;
+    let mut d = Deque::new();
+    d.push_back("a");
+    d.push_back("b|c");
+    let mut d2 = d.clone();
+    d2.push_back("d");
+
+    let mut g = Graph::new();
+    g.add("d", &d);
+    g.add("d2", &d2);
+    // a reversed copy shares the nodes, its digits swap places
+    let mut r = d.clone();
+    r.reverse();
+    g.add("r", &r);
+    assert_eq!(
+"digraph {
+    node [shape=record];
+    \"d\" [shape=plaintext];
+    n0 [label=\"Deque\"];
+    n1 [label=\"deep\"];
+    n2 [label=\"\\\"a\\\"|rc 2\", style=filled, fillcolor=lightgrey];
+    n1 -> n2 [label=\"pr\"];
+    n3 [label=\"empty|rc 2\", style=filled, fillcolor=lightgrey];
+    n1 -> n3 [label=\"mid\"];
+    n4 [label=\"\\\"b\\|c\\\"|rc 2\", style=filled, fillcolor=lightgrey];
+    n1 -> n4 [label=\"sf\"];
+    n0 -> n1;
+    \"d\" -> n0;
+    \"d2\" [shape=plaintext];
+    n5 [label=\"Deque\"];
+    n6 [label=\"deep\"];
+    n6 -> n2 [label=\"pr\"];
+    n6 -> n3 [label=\"mid\"];
+    n6 -> n4 [label=\"sf\"];
+    n7 [label=\"\\\"d\\\"|rc 1\"];
+    n6 -> n7 [label=\"sf\"];
+    n5 -> n6;
+    \"d2\" -> n5;
+    \"r\" [shape=plaintext];
+    n8 [label=\"Deque\"];
+    n9 [label=\"deep\"];
+    n9 -> n4 [label=\"pr\"];
+    n9 -> n3 [label=\"mid\"];
+    n9 -> n2 [label=\"sf\"];
+    n8 -> n9;
+    \"r\" -> n8;
+}
+", g.render());
+}
+
+#[test]
+fn dot_spinelist() {
+    use spinelist::SpineList;
+    let mut l = SpineList::new();
+    l.prepend("b");
+    l.prepend("a");
+    let mut g = Graph::new();
+    g.add("l", &l);
+    g.add("empty", &SpineList::<i32>::new());
+    assert_eq!(
+"digraph {
+    node [shape=record];
+    \"l\" [shape=plaintext];
+    n0 [label=\"\\\"a\\\"\"];
+    n1 [label=\"\\\"b\\\"\"];
+    n0 -> n1;
+    \"l\" -> n0;
+    \"empty\" [shape=plaintext];
+    n2 [label=\"nil\"];
+    \"empty\" -> n2;
+}
+", g.render());
+}
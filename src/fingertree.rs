@@ -0,0 +1,1211 @@
+use refs::*;
+use stats::{MemoryStats, NodeVisitor, vec_bytes};
+use dot::{ToDot, Graph};
+use show::Disp;
+use std::fmt;
+use std::fmt::Debug;
+use std::iter::{Iterator, FromIterator};
+use std::mem::replace;
+use std::ops::Index;
+
+// A 2-3 finger tree (Hinze and Paterson, "Finger trees: a simple
+// general-purpose data structure"), annotated with measures. Elements sit in
+// `Leaf` nodes; the middle tree of a `Deep` holds `Node2`/`Node3`s of the
+// level above, which keeps the type uniform instead of nesting it.
+//
+// Reversing a tree only marks its middle tree and the nodes of its digits as
+// reversed (`Tree::Rev`, `Node::Rev`). The operations that take a tree apart
+// put the top of a marked tree the right way round first, which pushes the
+// marks one level down, so they stay O(log n) whichever way the parts run.
+// The measure of a reversed run is that of the run, so this is only done
+// with `Size`, which does not depend on the order of the elements.
+
+/// A monoid summarizing a run of elements. `combine` must be associative and
+/// `zero` its neutral element.
+pub trait Measure<T>: Clone {
+    fn zero() -> Self;
+    fn of(x: &T) -> Self;
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// Counts elements, turning a finger tree into an indexed sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Size(pub usize);
+
+impl <T> Measure<T> for Size {
+    fn zero() -> Size {
+        Size(0)
+    }
+
+    fn of(_: &T) -> Size {
+        Size(1)
+    }
+
+    fn combine(&self, other: &Size) -> Size {
+        Size(self.0 + other.0)
+    }
+}
+
+/// The greatest element, turning a finger tree into a priority search
+/// sequence.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Max<T>(pub Option<T>);
+
+impl <T: Ord+Clone> Measure<T> for Max<T> {
+    fn zero() -> Max<T> {
+        Max(None)
+    }
+
+    fn of(x: &T) -> Max<T> {
+        Max(Some(x.clone()))
+    }
+
+    fn combine(&self, other: &Max<T>) -> Max<T> {
+        match (&self.0, &other.0) {
+            (&Some(ref x), &Some(ref y)) => Max(Some(if x >= y { x.clone() } else { y.clone() })),
+            (&None, _)                   => other.clone(),
+            (_, &None)                   => self.clone()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node<T: Clone, M: Measure<T>> {
+    Leaf(T),
+    Node2(M, Ref<Node<T, M>>, Ref<Node<T, M>>),
+    Node3(M, Ref<Node<T, M>>, Ref<Node<T, M>>, Ref<Node<T, M>>),
+    // the node below with its elements in reverse order, never a leaf or
+    // another `Rev`
+    Rev(Ref<Node<T, M>>),
+}
+
+type Digit<T, M> = Vec<Ref<Node<T, M>>>;
+
+impl <T: Clone, M: Measure<T>> Node<T, M> {
+    fn measure(&self) -> M {
+        match *self {
+            Node::Leaf(ref x)             => M::of(x),
+            Node::Node2(ref m, _, _)      => m.clone(),
+            Node::Node3(ref m, _, _, _)   => m.clone(),
+            Node::Rev(ref x)              => x.measure()
+        }
+    }
+
+    fn children(&self) -> Digit<T, M> {
+        match *self {
+            Node::Leaf(_)                          => panic!("finger tree: a leaf has no children"),
+            Node::Node2(_, ref a, ref b)           => vec![a.clone(), b.clone()],
+            Node::Node3(_, ref a, ref b, ref c)    => vec![a.clone(), b.clone(), c.clone()],
+            Node::Rev(ref x)                       => flip_digit(&x.children())
+        }
+    }
+
+    fn into_leaf(self) -> T {
+        match self {
+            Node::Leaf(x) => x,
+            _             => panic!("finger tree: expected a leaf")
+        }
+    }
+
+    fn first(&self) -> &T {
+        match *self {
+            Node::Leaf(ref x)           => x,
+            Node::Node2(_, ref a, _)    => a.first(),
+            Node::Node3(_, ref a, _, _) => a.first(),
+            Node::Rev(ref x)            => x.last()
+        }
+    }
+
+    fn last(&self) -> &T {
+        match *self {
+            Node::Leaf(ref x)           => x,
+            Node::Node2(_, _, ref b)    => b.last(),
+            Node::Node3(_, _, _, ref c) => c.last(),
+            Node::Rev(ref x)            => x.first()
+        }
+    }
+
+    // The leaf at which `pred` turns true and the measure of everything to
+    // its left, given the measure `acc` of everything to the left of this node.
+    // With `rev`, the node is read back to front.
+    fn find<F: Fn(&M) -> bool>(&self, pred: &F, acc: M, rev: bool) -> (&T, M) {
+        match *self {
+            Node::Leaf(ref x) => (x, acc),
+            Node::Node2(_, ref a, ref b) => {
+                let kids = if rev { [b, a] } else { [a, b] };
+                let (x, acc) = digit_find(kids.iter().map(|x| *x), pred, acc);
+                x.find(pred, acc, rev)
+            }
+            Node::Node3(_, ref a, ref b, ref c) => {
+                let kids = if rev { [c, b, a] } else { [a, b, c] };
+                let (x, acc) = digit_find(kids.iter().map(|x| *x), pred, acc);
+                x.find(pred, acc, rev)
+            }
+            Node::Rev(ref x) => x.find(pred, acc, !rev)
+        }
+    }
+
+    // Collects the leaves below this node whose measure satisfies `pred`,
+    // skipping the subtrees whose measure does not.
+    fn filter_into<'a, F: Fn(&M) -> bool>(&'a self, pred: &F, out: &mut Vec<&'a T>, rev: bool) {
+        if !pred(&self.measure()) {
+            return
+        }
+        match *self {
+            Node::Leaf(ref x)                   => out.push(x),
+            Node::Node2(_, ref a, ref b)        => {
+                for x in &if rev { [b, a] } else { [a, b] } {
+                    x.filter_into(pred, out, rev);
+                }
+            }
+            Node::Node3(_, ref a, ref b, ref c) => {
+                for x in &if rev { [c, b, a] } else { [a, b, c] } {
+                    x.filter_into(pred, out, rev);
+                }
+            }
+            Node::Rev(ref x) => x.filter_into(pred, out, !rev)
+        }
+    }
+}
+
+// The node with its elements in reverse order, in O(1).
+fn flip<T: Clone, M: Measure<T>>(x: &Ref<Node<T, M>>) -> Ref<Node<T, M>> {
+    match **x {
+        Node::Leaf(_)    => x.clone(),
+        Node::Rev(ref y) => y.clone(),
+        _                => Ref::new(Node::Rev(x.clone()))
+    }
+}
+
+// The digit with its elements in reverse order.
+fn flip_digit<T: Clone, M: Measure<T>>(d: &[Ref<Node<T, M>>]) -> Digit<T, M> {
+    d.iter().rev().map(flip).collect()
+}
+
+fn node2<T: Clone, M: Measure<T>>(a: Ref<Node<T, M>>, b: Ref<Node<T, M>>) -> Ref<Node<T, M>> {
+    let m = a.measure().combine(&b.measure());
+    Ref::new(Node::Node2(m, a, b))
+}
+
+fn node3<T: Clone, M: Measure<T>>(a: Ref<Node<T, M>>, b: Ref<Node<T, M>>, c: Ref<Node<T, M>>) -> Ref<Node<T, M>> {
+    let m = a.measure().combine(&b.measure()).combine(&c.measure());
+    Ref::new(Node::Node3(m, a, b, c))
+}
+
+// Groups 2 to 12 nodes into `Node2`s and `Node3`s.
+fn nodes<T: Clone, M: Measure<T>>(xs: Digit<T, M>) -> Digit<T, M> {
+    let mut ret = Vec::with_capacity(xs.len() / 2);
+    let mut rest = xs.len();
+    let mut xs = xs.into_iter();
+    while rest > 0 {
+        if rest == 2 || rest == 4 {
+            let a = xs.next().unwrap();
+            let b = xs.next().unwrap();
+            ret.push(node2(a, b));
+            rest -= 2;
+        } else {
+            let a = xs.next().unwrap();
+            let b = xs.next().unwrap();
+            let c = xs.next().unwrap();
+            ret.push(node3(a, b, c));
+            rest -= 3;
+        }
+    }
+    ret
+}
+
+fn digit_measure<T: Clone, M: Measure<T>>(d: &[Ref<Node<T, M>>]) -> M {
+    d.iter().fold(M::zero(), |acc, x| acc.combine(&x.measure()))
+}
+
+// The node of a non-empty digit at which `pred` turns true (or the last one),
+// and the measure of the nodes before it.
+fn digit_find<'a, T: Clone+'a, M: Measure<T>+'a, I, F>(d: I, pred: &F, acc: M) -> (&'a Node<T, M>, M)
+    where I: Iterator<Item=&'a Ref<Node<T, M>>>, F: Fn(&M) -> bool {
+    let mut acc = acc;
+    let mut d = d.peekable();
+    loop {
+        let x : &Node<T, M> = d.next().expect("finger tree: empty digit");
+        if d.peek().is_none() {
+            return (x, acc)
+        }
+        let next_acc = acc.combine(&x.measure());
+        if pred(&next_acc) {
+            return (x, acc)
+        }
+        acc = next_acc;
+    }
+}
+
+// `digit_find` on a digit, read back to front with `rev`.
+fn digit_find_in<'a, T: Clone, M: Measure<T>, F>(d: &'a [Ref<Node<T, M>>], rev: bool, pred: &F, acc: M) -> (&'a Node<T, M>, M)
+    where F: Fn(&M) -> bool {
+    if rev {
+        digit_find(d.iter().rev(), pred, acc)
+    } else {
+        digit_find(d.iter(), pred, acc)
+    }
+}
+
+// Splits a non-empty digit like `digit_find`.
+fn split_digit<T: Clone, M: Measure<T>, F>(d: Digit<T, M>, pred: &F, acc: M) -> (Digit<T, M>, Ref<Node<T, M>>, Digit<T, M>)
+    where F: Fn(&M) -> bool {
+    let mut acc = acc;
+    let mut left = Vec::with_capacity(d.len());
+    let mut d = d.into_iter().peekable();
+    loop {
+        let x = d.next().expect("finger tree: empty digit");
+        if d.peek().is_none() {
+            return (left, x, vec![])
+        }
+        acc = acc.combine(&x.measure());
+        if pred(&acc) {
+            return (left, x, d.collect())
+        }
+        left.push(x);
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Tree<T: Clone, M: Measure<T>> {
+    Empty,
+    Single(Ref<Node<T, M>>),
+    Deep(M, Digit<T, M>, Ref<Tree<T, M>>, Digit<T, M>),
+    // the tree below with its elements in reverse order; only ever a middle
+    // tree, never empty and never over another `Rev`
+    Rev(Ref<Tree<T, M>>),
+}
+
+fn deep<T: Clone, M: Measure<T>>(pr: Digit<T, M>, m: Tree<T, M>, sf: Digit<T, M>) -> Tree<T, M> {
+    let measure = digit_measure(&pr).combine(&m.measure()).combine(&digit_measure(&sf));
+    Tree::Deep(measure, pr, Ref::new(m), sf)
+}
+
+// Like `deep`, but `pr` may be empty and is then refilled from `m`.
+fn deep_l<T: Clone, M: Measure<T>>(pr: Digit<T, M>, m: Tree<T, M>, sf: Digit<T, M>) -> Tree<T, M> {
+    if !pr.is_empty() {
+        return deep(pr, m, sf)
+    }
+    match m.view_front() {
+        None          => Tree::from_digit(sf),
+        Some((n, m2)) => deep(n.children(), m2, sf)
+    }
+}
+
+// Like `deep`, but `sf` may be empty and is then refilled from `m`.
+fn deep_r<T: Clone, M: Measure<T>>(pr: Digit<T, M>, m: Tree<T, M>, sf: Digit<T, M>) -> Tree<T, M> {
+    if !sf.is_empty() {
+        return deep(pr, m, sf)
+    }
+    match m.view_back() {
+        None          => Tree::from_digit(pr),
+        Some((n, m2)) => deep(pr, m2, n.children())
+    }
+}
+
+// The middle tree with its elements in reverse order, in O(1).
+fn rev_tree<T: Clone, M: Measure<T>>(m: &Ref<Tree<T, M>>) -> Ref<Tree<T, M>> {
+    match **m {
+        Tree::Empty      => m.clone(),
+        Tree::Rev(ref t) => t.clone(),
+        _                => Ref::new(Tree::Rev(m.clone()))
+    }
+}
+
+fn app3<T: Clone, M: Measure<T>>(t1: Tree<T, M>, ts: Digit<T, M>, t2: Tree<T, M>) -> Tree<T, M> {
+    match (t1.open(), t2.open()) {
+        (Tree::Empty, t2) => ts.into_iter().rev().fold(t2, |t, x| t.push_front(x)),
+        (t1, Tree::Empty) => ts.into_iter().fold(t1, |t, x| t.push_back(x)),
+        (Tree::Single(x), t2) => app3(Tree::Empty, ts, t2).push_front(x),
+        (t1, Tree::Single(x)) => app3(t1, ts, Tree::Empty).push_back(x),
+        (Tree::Deep(_, pr1, m1, sf1), Tree::Deep(_, pr2, m2, sf2)) => {
+            let mut mid = sf1;
+            mid.extend(ts);
+            mid.extend(pr2);
+            let m = app3(m1.consume(), nodes(mid), m2.consume());
+            deep(pr1, m, sf2)
+        }
+        _ => unreachable!()
+    }
+}
+
+impl <T: Clone, M: Measure<T>> Tree<T, M> {
+    fn from_digit(d: Digit<T, M>) -> Tree<T, M> {
+        d.into_iter().fold(Tree::Empty, |t, x| t.push_back(x))
+    }
+
+    fn measure(&self) -> M {
+        match *self {
+            Tree::Empty                => M::zero(),
+            Tree::Single(ref x)        => x.measure(),
+            Tree::Deep(ref m, _, _, _) => m.clone(),
+            Tree::Rev(ref t)           => t.measure()
+        }
+    }
+
+    // The elements in reverse order, in O(1): the digits swap places and
+    // their nodes are flipped, the middle tree is only marked.
+    fn reversed(self) -> Tree<T, M> {
+        match self {
+            Tree::Empty              => Tree::Empty,
+            Tree::Single(x)          => Tree::Single(flip(&x)),
+            Tree::Deep(v, pr, m, sf) => Tree::Deep(v, flip_digit(&sf), rev_tree(&m), flip_digit(&pr)),
+            Tree::Rev(t)             => t.consume()
+        }
+    }
+
+    // Puts the top of a reversed tree the right way round, so that its
+    // digits can be taken apart.
+    fn open(self) -> Tree<T, M> {
+        match self {
+            Tree::Rev(t) => t.consume().reversed(),
+            t            => t
+        }
+    }
+
+    fn push_front(self, a: Ref<Node<T, M>>) -> Tree<T, M> {
+        match self.open() {
+            Tree::Empty     => Tree::Single(a),
+            Tree::Rev(_)    => unreachable!(),
+            Tree::Single(b) => deep(vec![a], Tree::Empty, vec![b]),
+            Tree::Deep(v, mut pr, m, sf) => {
+                let v = a.measure().combine(&v);
+                if pr.len() == 4 {
+                    let e = pr.pop().unwrap();
+                    let d = pr.pop().unwrap();
+                    let c = pr.pop().unwrap();
+                    let b = pr.pop().unwrap();
+                    let m = m.consume().push_front(node3(c, d, e));
+                    Tree::Deep(v, vec![a, b], Ref::new(m), sf)
+                } else {
+                    pr.insert(0, a);
+                    Tree::Deep(v, pr, m, sf)
+                }
+            }
+        }
+    }
+
+    fn push_back(self, a: Ref<Node<T, M>>) -> Tree<T, M> {
+        match self.open() {
+            Tree::Empty     => Tree::Single(a),
+            Tree::Rev(_)    => unreachable!(),
+            Tree::Single(b) => deep(vec![b], Tree::Empty, vec![a]),
+            Tree::Deep(v, pr, m, mut sf) => {
+                let v = v.combine(&a.measure());
+                if sf.len() == 4 {
+                    let e = sf.pop().unwrap();
+                    let d = sf.pop().unwrap();
+                    let c = sf.pop().unwrap();
+                    let b = sf.pop().unwrap();
+                    let m = m.consume().push_back(node3(b, c, d));
+                    Tree::Deep(v, pr, Ref::new(m), vec![e, a])
+                } else {
+                    sf.push(a);
+                    Tree::Deep(v, pr, m, sf)
+                }
+            }
+        }
+    }
+
+    fn view_front(self) -> Option<(Ref<Node<T, M>>, Tree<T, M>)> {
+        match self.open() {
+            Tree::Empty     => None,
+            Tree::Rev(_)    => unreachable!(),
+            Tree::Single(x) => Some((x, Tree::Empty)),
+            Tree::Deep(_, mut pr, m, sf) => {
+                let x = pr.remove(0);
+                Some((x, deep_l(pr, m.consume(), sf)))
+            }
+        }
+    }
+
+    fn view_back(self) -> Option<(Ref<Node<T, M>>, Tree<T, M>)> {
+        match self.open() {
+            Tree::Empty     => None,
+            Tree::Rev(_)    => unreachable!(),
+            Tree::Single(x) => Some((x, Tree::Empty)),
+            Tree::Deep(_, pr, m, mut sf) => {
+                let x = sf.pop().unwrap();
+                Some((x, deep_r(pr, m.consume(), sf)))
+            }
+        }
+    }
+
+    fn front(&self) -> Option<&T> {
+        match *self {
+            Tree::Empty                  => None,
+            Tree::Single(ref x)          => Some(x.first()),
+            Tree::Deep(_, ref pr, _, _)  => Some(pr[0].first()),
+            Tree::Rev(ref t)             => t.back()
+        }
+    }
+
+    fn back(&self) -> Option<&T> {
+        match *self {
+            Tree::Empty                  => None,
+            Tree::Single(ref x)          => Some(x.last()),
+            Tree::Deep(_, _, _, ref sf)  => Some(sf[sf.len() - 1].last()),
+            Tree::Rev(ref t)             => t.front()
+        }
+    }
+
+    // Splits a non-empty tree around the node at which `pred` turns true,
+    // given the measure `acc` of everything to the left of the tree.
+    fn split<F: Fn(&M) -> bool>(self, pred: &F, acc: M) -> (Tree<T, M>, Ref<Node<T, M>>, Tree<T, M>) {
+        match self.open() {
+            Tree::Empty     => panic!("finger tree: split of an empty tree"),
+            Tree::Rev(_)    => unreachable!(),
+            Tree::Single(x) => (Tree::Empty, x, Tree::Empty),
+            Tree::Deep(_, pr, m, sf) => {
+                let acc_pr = acc.combine(&digit_measure(&pr));
+                if pred(&acc_pr) {
+                    let (l, x, r) = split_digit(pr, pred, acc);
+                    return (Tree::from_digit(l), x, deep_l(r, m.consume(), sf))
+                }
+                let m = m.consume();
+                let acc_m = acc_pr.combine(&m.measure());
+                if pred(&acc_m) {
+                    let (ml, xs, mr) = m.split(pred, acc_pr.clone());
+                    let acc_ml = acc_pr.combine(&ml.measure());
+                    let (l, x, r) = split_digit(xs.children(), pred, acc_ml);
+                    (deep_r(pr, ml, l), x, deep_l(r, mr, sf))
+                } else {
+                    let (l, x, r) = split_digit(sf, pred, acc_m);
+                    (deep_r(pr, m, l), x, Tree::from_digit(r))
+                }
+            }
+        }
+    }
+
+    // Like `split`, without taking the tree apart. With `rev`, the tree is
+    // read back to front.
+    fn find<F: Fn(&M) -> bool>(&self, pred: &F, acc: M, rev: bool) -> Option<(&T, M)> {
+        match *self {
+            Tree::Empty         => None,
+            Tree::Single(ref x) => Some(x.find(pred, acc, rev)),
+            Tree::Deep(_, ref pr, ref m, ref sf) => {
+                let (pr, sf) = if rev { (sf, pr) } else { (pr, sf) };
+                let acc_pr = acc.combine(&digit_measure(pr));
+                if pred(&acc_pr) {
+                    let (x, acc) = digit_find_in(pr, rev, pred, acc);
+                    return Some(x.find(pred, acc, rev))
+                }
+                let m : &Tree<T, M> = m;
+                let acc_m = acc_pr.combine(&m.measure());
+                if pred(&acc_m) {
+                    return m.find(pred, acc_pr, rev)
+                }
+                let (x, acc) = digit_find_in(sf, rev, pred, acc_m);
+                Some(x.find(pred, acc, rev))
+            }
+            Tree::Rev(ref t) => t.find(pred, acc, !rev)
+        }
+    }
+
+    fn filter_into<'a, F: Fn(&M) -> bool>(&'a self, pred: &F, out: &mut Vec<&'a T>, rev: bool) {
+        match *self {
+            Tree::Empty         => {}
+            Tree::Single(ref x) => x.filter_into(pred, out, rev),
+            Tree::Deep(ref m, ref pr, ref mid, ref sf) => {
+                if !pred(m) {
+                    return
+                }
+                let (pr, sf) = if rev { (sf, pr) } else { (pr, sf) };
+                let pr : Vec<_> = if rev { pr.iter().rev().collect() } else { pr.iter().collect() };
+                let sf : Vec<_> = if rev { sf.iter().rev().collect() } else { sf.iter().collect() };
+                for x in pr {
+                    x.filter_into(pred, out, rev);
+                }
+                mid.filter_into(pred, out, rev);
+                for x in sf {
+                    x.filter_into(pred, out, rev);
+                }
+            }
+            Tree::Rev(ref t) => t.filter_into(pred, out, !rev)
+        }
+    }
+}
+
+fn visit_node<T: Clone, M: Measure<T>, F: Fn(&T) -> usize>(x: &Ref<Node<T, M>>, v: &mut NodeVisitor, elt_bytes: &F) {
+    let extra = match **x {
+        Node::Leaf(ref x) => elt_bytes(x),
+        _                 => 0
+    };
+    if !v.node_ref(x, extra) {
+        return
+    }
+    match **x {
+        Node::Leaf(_)                       => {}
+        Node::Rev(ref x)                    => visit_node(x, v, elt_bytes),
+        Node::Node2(_, ref a, ref b)        => {
+            visit_node(a, v, elt_bytes);
+            visit_node(b, v, elt_bytes);
+        }
+        Node::Node3(_, ref a, ref b, ref c) => {
+            visit_node(a, v, elt_bytes);
+            visit_node(b, v, elt_bytes);
+            visit_node(c, v, elt_bytes);
+        }
+    }
+}
+
+impl <T: Clone, M: Measure<T>> Tree<T, M> {
+    // The digits of the top level live in the `FingerTree` itself and are
+    // not counted; those of the levels below are part of their `Ref<Tree>`.
+    fn visit_into<F: Fn(&T) -> usize>(&self, v: &mut NodeVisitor, elt_bytes: &F) {
+        match *self {
+            Tree::Empty         => {}
+            Tree::Single(ref x) => visit_node(x, v, elt_bytes),
+            Tree::Deep(_, ref pr, ref mid, ref sf) => {
+                for x in pr {
+                    visit_node(x, v, elt_bytes);
+                }
+                if v.node_ref(mid, mid.digit_bytes()) {
+                    mid.visit_into(v, elt_bytes);
+                }
+                for x in sf {
+                    visit_node(x, v, elt_bytes);
+                }
+            }
+            Tree::Rev(ref t) => {
+                if v.node_ref(t, t.digit_bytes()) {
+                    t.visit_into(v, elt_bytes);
+                }
+            }
+        }
+    }
+
+    fn digit_bytes(&self) -> usize {
+        match *self {
+            Tree::Deep(_, ref pr, _, ref sf) => vec_bytes(pr) + vec_bytes(sf),
+            _                                => 0
+        }
+    }
+}
+
+fn dot_node<T: Clone+Debug, M: Measure<T>>(x: &Ref<Node<T, M>>, g: &mut Graph) -> String {
+    let fields = match **x {
+        Node::Leaf(ref x)  => vec![format!("{:?}", x)],
+        Node::Node2(..)    => vec!["node2".to_string()],
+        Node::Node3(..)    => vec!["node3".to_string()],
+        Node::Rev(..)      => vec!["rev".to_string()]
+    };
+    let (id, new) = g.node_ref(x, &fields);
+    if new {
+        match **x {
+            Node::Leaf(_)                       => {}
+            Node::Rev(ref a)                    => {
+                let n = dot_node(a, g);
+                g.edge(&id, &n, "");
+            }
+            Node::Node2(_, ref a, ref b)        => {
+                for child in &[a, b] {
+                    let n = dot_node(child, g);
+                    g.edge(&id, &n, "");
+                }
+            }
+            Node::Node3(_, ref a, ref b, ref c) => {
+                for child in &[a, b, c] {
+                    let n = dot_node(child, g);
+                    g.edge(&id, &n, "");
+                }
+            }
+        }
+    }
+    id
+}
+
+impl <T: Clone+Debug, M: Measure<T>> Tree<T, M> {
+    fn dot_label(&self) -> Vec<String> {
+        let name = match *self {
+            Tree::Empty     => "empty",
+            Tree::Single(_) => "single",
+            Tree::Deep(..)  => "deep",
+            Tree::Rev(_)    => "rev"
+        };
+        vec![name.to_string()]
+    }
+
+    // Adds the edges from `id`, the node drawn for this tree.
+    fn dot_into(&self, id: &str, g: &mut Graph) {
+        match *self {
+            Tree::Empty         => {}
+            Tree::Single(ref x) => {
+                let x = dot_node(x, g);
+                g.edge(id, &x, "");
+            }
+            Tree::Deep(_, ref pr, ref mid, ref sf) => {
+                for x in pr {
+                    let x = dot_node(x, g);
+                    g.edge(id, &x, "pr");
+                }
+                let (m, new) = g.node_ref(mid, &mid.dot_label());
+                if new {
+                    mid.dot_into(&m, g);
+                }
+                g.edge(id, &m, "mid");
+                for x in sf {
+                    let x = dot_node(x, g);
+                    g.edge(id, &x, "sf");
+                }
+            }
+            Tree::Rev(ref t) => {
+                let (n, new) = g.node_ref(t, &t.dot_label());
+                if new {
+                    t.dot_into(&n, g);
+                }
+                g.edge(id, &n, "");
+            }
+        }
+    }
+}
+
+/// A persistent sequence with amortized O(1) access to both ends and
+/// O(log n) concatenation and splitting. Splits and searches are guided by
+/// the measure `M` of the elements, which every node caches for its subtree.
+#[derive(Clone)]
+pub struct FingerTree<T: Clone, M: Measure<T>> {
+    tree : Tree<T, M>,
+}
+
+/// A sequence indexed by position.
+pub type IndexedSeq<T> = FingerTree<T, Size>;
+
+/// A sequence that finds and removes its greatest element in O(log n).
+pub type PrioritySeq<T> = FingerTree<T, Max<T>>;
+
+impl <T: Clone, M: Measure<T>> FingerTree<T, M> {
+    pub fn new() -> FingerTree<T, M> {
+        FingerTree { tree: Tree::Empty }
+    }
+
+    /// The measure of all elements, in O(1).
+    pub fn measure(&self) -> M {
+        self.tree.measure()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self.tree {
+            Tree::Empty => true,
+            _           => false
+        }
+    }
+
+    pub fn push_front(&mut self, x: T) {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        self.tree = tree.push_front(Ref::new(Node::Leaf(x)));
+    }
+
+    pub fn push_back(&mut self, x: T) {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        self.tree = tree.push_back(Ref::new(Node::Leaf(x)));
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        tree.view_front().map(|(x, rest)| {
+            self.tree = rest;
+            x.consume().into_leaf()
+        })
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        tree.view_back().map(|(x, rest)| {
+            self.tree = rest;
+            x.consume().into_leaf()
+        })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.tree.front()
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.tree.back()
+    }
+
+    /// Moves all elements of `other` to the end of `self` in O(log n).
+    pub fn append(&mut self, other: FingerTree<T, M>) {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        self.tree = app3(tree, vec![], other.tree);
+    }
+
+    /// Splits the sequence before the first element at which `pred`, applied
+    /// to the measure of all elements up to and including it, turns true.
+    /// `pred` must be monotone: once true, it stays true for longer prefixes.
+    pub fn split<F: Fn(&M) -> bool>(self, pred: F) -> (FingerTree<T, M>, FingerTree<T, M>) {
+        if self.is_empty() || !pred(&self.measure()) {
+            return (self, FingerTree::new())
+        }
+        let (l, x, r) = self.tree.split(&pred, M::zero());
+        (FingerTree { tree: l }, FingerTree { tree: r.push_front(x) })
+    }
+
+    /// The first element at which `pred` turns true, see `split`.
+    pub fn find<F: Fn(&M) -> bool>(&self, pred: F) -> Option<&T> {
+        self.find_with_measure(pred).map(|(x, _)| x)
+    }
+
+    /// Like `find`, also returning the measure of the elements before the
+    /// one found.
+    pub fn find_with_measure<F: Fn(&M) -> bool>(&self, pred: F) -> Option<(&T, M)> {
+        if self.is_empty() || !pred(&self.measure()) {
+            return None
+        }
+        self.tree.find(&pred, M::zero(), false)
+    }
+
+    /// The elements whose own measure satisfies `pred`, in order. Subtrees
+    /// whose measure fails `pred` are skipped, so whenever `pred` holds for an
+    /// element, it must also hold for every run of elements containing it.
+    pub fn filter<F: Fn(&M) -> bool>(&self, pred: F) -> Vec<&T> {
+        let mut ret = vec![];
+        self.tree.filter_into(&pred, &mut ret, false);
+        ret
+    }
+
+    pub fn iter(&self) -> Iter<T, M> {
+        self.clone().to_iter()
+    }
+
+    pub fn to_iter(self) -> Iter<T, M> {
+        Iter { tree: self }
+    }
+
+    /// Iterates over references to the elements, in order, without cloning
+    /// them or the tree.
+    pub fn elts(&self) -> Elts<'_, T, M> {
+        let mut stack = vec![];
+        push_tree(&self.tree, false, false, &mut stack);
+        Elts { stack: stack }
+    }
+
+    /// Like `visit_nodes`, also counting `elt_bytes(x)` bytes for the heap
+    /// memory owned by each element `x`.
+    pub fn visit_nodes_with<F: Fn(&T) -> usize>(&self, v: &mut NodeVisitor, elt_bytes: &F) {
+        self.tree.visit_into(v, elt_bytes)
+    }
+}
+
+/// `[1, 2, 3]`, or with `{:#?}` the tree with its digits and measures.
+impl <T: Clone+Debug, M: Measure<T>+Debug> Debug for FingerTree<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("FingerTree").field("tree", &self.tree).finish()
+        }
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl <T: Clone+fmt::Display, M: Measure<T>> fmt::Display for FingerTree<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+// The top level is held inline, so it gets a node of its own in every graph.
+impl <T: Clone+Debug, M: Measure<T>> ToDot for FingerTree<T, M> {
+    fn to_dot(&self, g: &mut Graph) -> String {
+        let id = g.record(&self.tree.dot_label());
+        self.tree.dot_into(&id, g);
+        id
+    }
+}
+
+impl <T: Clone, M: Measure<T>> MemoryStats for FingerTree<T, M> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.visit_nodes_with(v, &|_| 0)
+    }
+}
+
+impl <T: Clone> FingerTree<T, Size> {
+    pub fn len(&self) -> usize {
+        self.measure().0
+    }
+
+    /// The `idx`th element, in O(log n).
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.find(|size| size.0 > idx)
+    }
+
+    /// Reverses the sequence in O(1). Only the top of the tree is turned
+    /// around; the levels below are marked and turned as later operations
+    /// reach them, so those stay O(log n).
+    pub fn reverse(&mut self) {
+        let tree = replace(&mut self.tree, Tree::Empty);
+        self.tree = tree.reversed();
+    }
+
+    /// Keeps the first `at` elements and returns the rest, in O(log n).
+    /// Panics if `at > len`.
+    pub fn split_off(&mut self, at: usize) -> FingerTree<T, Size> {
+        assert!(at <= self.len(), "finger tree: split index out of bounds");
+        let seq = replace(self, FingerTree::new());
+        let (l, r) = seq.split(|size| size.0 > at);
+        *self = l;
+        r
+    }
+}
+
+// A subtree met while walking two trees side by side, and whether it is
+// read back to front.
+enum Piece<'a, T: Clone+'a, M: Measure<T>+'a> {
+    Node(&'a Ref<Node<T, M>>, bool),
+    Tree(&'a Ref<Tree<T, M>>, bool),
+}
+
+// Pushes the parts of `t`, read back to front with `rev`, on `stack`, so
+// that its first one, or with `from_back` its last one, is on top.
+fn push_tree<'a, T: Clone, M: Measure<T>>(t: &'a Tree<T, M>, rev: bool, from_back: bool, stack: &mut Vec<Piece<'a, T, M>>) {
+    match *t {
+        Tree::Empty => {}
+        Tree::Single(ref x) => stack.push(Piece::Node(x, rev)),
+        Tree::Deep(_, ref pr, ref m, ref sf) => {
+            let node = |x| Piece::Node(x, rev);
+            if from_back != rev {
+                stack.extend(pr.iter().map(node));
+                stack.push(Piece::Tree(m, rev));
+                stack.extend(sf.iter().map(node));
+            } else {
+                stack.extend(sf.iter().rev().map(node));
+                stack.push(Piece::Tree(m, rev));
+                stack.extend(pr.iter().rev().map(node));
+            }
+        }
+        Tree::Rev(ref t) => push_tree(t, !rev, from_back, stack)
+    }
+}
+
+impl <'a, T: Clone, M: Measure<T>> Piece<'a, T, M> {
+    fn measure(&self) -> M {
+        match *self {
+            Piece::Node(n, _) => n.measure(),
+            Piece::Tree(t, _) => t.measure()
+        }
+    }
+
+    // A leaf reads the same both ways.
+    fn same(&self, other: &Piece<'a, T, M>) -> bool {
+        match (self, other) {
+            (&Piece::Node(a, ra), &Piece::Node(b, rb)) => {
+                let leaf = match **a { Node::Leaf(_) => true, _ => false };
+                (ra == rb || leaf) && Rc::ptr_eq(&a._ptr, &b._ptr)
+            }
+            (&Piece::Tree(a, ra), &Piece::Tree(b, rb)) => ra == rb && Rc::ptr_eq(&a._ptr, &b._ptr),
+            _                                          => false
+        }
+    }
+
+    // Replaces this piece on `stack` with its parts. Returns false for a
+    // leaf, which has none.
+    fn expand(&self, from_back: bool, stack: &mut Vec<Piece<'a, T, M>>) -> bool {
+        match *self {
+            Piece::Tree(t, rev) => push_tree(t, rev, from_back, stack),
+            Piece::Node(n, rev) => {
+                let children : Vec<&'a Ref<Node<T, M>>> = match **n {
+                    Node::Leaf(_)                       => return false,
+                    Node::Node2(_, ref a, ref b)        => vec![a, b],
+                    Node::Node3(_, ref a, ref b, ref c) => vec![a, b, c],
+                    Node::Rev(ref a)                    => {
+                        stack.push(Piece::Node(a, !rev));
+                        return true
+                    }
+                };
+                if from_back != rev {
+                    stack.extend(children.into_iter().map(|x| Piece::Node(x, rev)));
+                } else {
+                    stack.extend(children.into_iter().rev().map(|x| Piece::Node(x, rev)));
+                }
+            }
+        }
+        true
+    }
+}
+
+impl <T: Clone+PartialEq> FingerTree<T, Size> {
+    /// The number of elements at the front of `self` that `other` starts
+    /// with too. Subtrees that the two share are skipped whole.
+    pub fn common_prefix_len(&self, other: &FingerTree<T, Size>) -> usize {
+        self.common_len(other, false)
+    }
+
+    /// Like `common_prefix_len`, at the back.
+    pub fn common_suffix_len(&self, other: &FingerTree<T, Size>) -> usize {
+        self.common_len(other, true)
+    }
+
+    // Walks both trees from one end, opening the larger of the two pieces
+    // met until they are the same subtree or two leaves. A middle tree counts
+    // as larger than a leaf, whatever it holds.
+    fn common_len(&self, other: &FingerTree<T, Size>, from_back: bool) -> usize {
+        let (mut xs, mut ys) = (vec![], vec![]);
+        push_tree(&self.tree, false, from_back, &mut xs);
+        push_tree(&other.tree, false, from_back, &mut ys);
+        let mut ret = 0;
+        while let (Some(x), Some(y)) = (xs.pop(), ys.pop()) {
+            let (nx, ny) = (x.measure().0, y.measure().0);
+            if x.same(&y) {
+                ret += nx;
+            } else if (nx >= ny || ny == 1) && x.expand(from_back, &mut xs) {
+                ys.push(y);
+            } else if y.expand(from_back, &mut ys) {
+                xs.push(x);
+            } else {
+                match (x, y) {
+                    (Piece::Node(a, _), Piece::Node(b, _)) => {
+                        if a.first() != b.first() {
+                            break
+                        }
+                        ret += 1;
+                    }
+                    _ => unreachable!()
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl <T: Clone> Index<usize> for FingerTree<T, Size> {
+    type Output = T;
+    fn index(&self, idx: usize) -> &T {
+        self.get(idx).expect("finger tree index out of bounds")
+    }
+}
+
+impl <T: Ord+Clone> FingerTree<T, Max<T>> {
+    /// The greatest element, the leftmost one if there are several.
+    pub fn max(&self) -> Option<&T> {
+        match self.measure() {
+            Max(None)      => None,
+            Max(Some(max)) => self.find(|acc| acc.0.as_ref().map_or(false, |x| *x >= max))
+        }
+    }
+
+    /// Removes the element `max` would return, in O(log n).
+    pub fn pop_max(&mut self) -> Option<T> {
+        let max = match self.measure() {
+            Max(None)      => return None,
+            Max(Some(max)) => max
+        };
+        let seq = replace(self, FingerTree::new());
+        let (mut l, mut r) = seq.split(|acc| acc.0.as_ref().map_or(false, |x| *x >= max));
+        let ret = r.pop_front();
+        l.append(r);
+        *self = l;
+        ret
+    }
+}
+
+pub struct Iter<T: Clone, M: Measure<T>> {
+    tree: FingerTree<T, M>,
+}
+
+impl <T: Clone, M: Measure<T>> Iterator for Iter<T, M> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.tree.pop_front()
+    }
+}
+
+pub struct Elts<'a, T: Clone+'a, M: Measure<T>+'a> {
+    stack: Vec<Piece<'a, T, M>>,
+}
+
+impl <'a, T: Clone, M: Measure<T>> Iterator for Elts<'a, T, M> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(piece) = self.stack.pop() {
+            if !piece.expand(false, &mut self.stack) {
+                if let Piece::Node(n, _) = piece {
+                    return Some(n.first())
+                }
+            }
+        }
+        None
+    }
+}
+
+impl <T: Clone, M: Measure<T>> FromIterator<T> for FingerTree<T, M> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> FingerTree<T, M> {
+        let mut ret = FingerTree::new();
+        for x in iter {
+            ret.push_back(x);
+        }
+        ret
+    }
+}
+
+
+#[test]
+fn ft_push_pop() {
+    let mut t = IndexedSeq::new();
+    for i in 0..100 {
+        t.push_back(i);
+        t.push_front(-i);
+    }
+    assert_eq!(200, t.len());
+    for i in (0..100).rev() {
+        assert_eq!(Some(-i), t.pop_front());
+    }
+    for i in (0..100).rev() {
+        assert_eq!(Some(i), t.pop_back());
+    }
+    assert_eq!(None, t.pop_front());
+    assert_eq!(None, t.pop_back());
+    assert!(t.is_empty());
+}
+
+#[test]
+fn ft_get() {
+    let t : IndexedSeq<usize> = (0..1000).collect();
+    for i in 0..1000 {
+        assert_eq!(Some(&i), t.get(i));
+        assert_eq!(i, t[i]);
+    }
+    assert_eq!(None, t.get(1000));
+    assert_eq!(Some(&0), t.front());
+    assert_eq!(Some(&999), t.back());
+}
+
+#[test]
+fn ft_append_split() {
+    for n in 0..50 {
+        for m in 0..50 {
+            let mut t : IndexedSeq<usize> = (0..n).collect();
+            t.append((n..n + m).collect());
+            assert_eq!(n + m, t.len());
+            assert_eq!((0..n + m).collect::<Vec<_>>(), t.iter().collect::<Vec<_>>());
+            assert_eq!((0..n + m).collect::<Vec<_>>(), t.elts().cloned().collect::<Vec<_>>());
+
+            let rest = t.split_off(n);
+            assert_eq!((0..n).collect::<Vec<_>>(), t.to_iter().collect::<Vec<_>>());
+            assert_eq!((n..n + m).collect::<Vec<_>>(), rest.to_iter().collect::<Vec<_>>());
+        }
+    }
+}
+
+#[test]
+fn ft_persistent() {
+    let t1 : IndexedSeq<usize> = (0..100).collect();
+    let mut t2 = t1.clone();
+    let t3 = t2.split_off(50);
+    assert_eq!(100, t1.len());
+    assert_eq!(50, t2.len());
+    assert_eq!(50, t3.len());
+    assert_eq!(Some(&99), t1.back());
+}
+
+#[test]
+fn ft_reverse() {
+    // random pushes, pops, splits and appends of trees reversed or not,
+    // against a vector
+    let mut seed : u32 = 5;
+    let mut t : IndexedSeq<u32> = (0..100).collect();
+    let mut v : Vec<u32> = (0..100).collect();
+    for i in 0..2000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let r = seed >> 16;
+        match r % 6 {
+            0 => { t.reverse(); v.reverse(); }
+            1 => { t.push_front(i); v.insert(0, i); }
+            2 => { t.push_back(i); v.push(i); }
+            3 => { assert_eq!(v.pop(), t.pop_back()); }
+            4 => {
+                let at = r as usize % (v.len() + 1);
+                let mut tail = t.split_off(at);
+                let mut w = v.split_off(at);
+                tail.reverse();
+                w.reverse();
+                tail.append(t);
+                w.extend(v);
+                t = tail;
+                v = w;
+            }
+            _ => {
+                let mut other = t.clone();
+                other.reverse();
+                t.append(other);
+                let w : Vec<u32> = v.iter().rev().cloned().collect();
+                v.extend(w);
+                if v.len() > 1000 {
+                    let rest = t.split_off(500);
+                    assert_eq!(v.split_off(500), rest.iter().collect::<Vec<_>>());
+                }
+            }
+        }
+        assert_eq!(v.len(), t.len());
+        assert_eq!(v.first(), t.front());
+        assert_eq!(v.last(), t.back());
+        if i % 50 == 0 {
+            assert_eq!(v, t.elts().cloned().collect::<Vec<_>>());
+            assert_eq!(v, t.iter().collect::<Vec<_>>());
+            assert_eq!(v.iter().collect::<Vec<_>>(), t.filter(|_| true));
+            for (j, x) in v.iter().enumerate() {
+                assert_eq!(Some(x), t.get(j));
+            }
+        }
+    }
+
+    // reversing twice gives back an equal tree, sharing its subtrees
+    let mut back = t.clone();
+    back.reverse();
+    back.reverse();
+    assert_eq!(t.len(), t.common_prefix_len(&back));
+    assert_eq!(t.len(), back.common_suffix_len(&t));
+}
+
+#[test]
+fn ft_common_len() {
+    use diff::NoEq;
+    // shared subtrees must be skipped without comparing their elements
+    let t1 : IndexedSeq<NoEq> = (0..1000).map(|_| NoEq).collect();
+    let mut t2 = t1.clone();
+    t2.push_back(NoEq);
+    assert_eq!(1000, t1.common_prefix_len(&t2));
+    let mut t3 = t1.clone();
+    t3.push_front(NoEq);
+    assert_eq!(1000, t1.common_suffix_len(&t3));
+    assert_eq!(1000, t3.common_suffix_len(&t1));
+
+    let a : IndexedSeq<usize> = (0..100).collect();
+    let b : IndexedSeq<usize> = (0..50).chain(1000..1010).chain(60..100).collect();
+    assert_eq!(50, a.common_prefix_len(&b));
+    assert_eq!(40, a.common_suffix_len(&b));
+    assert_eq!(100, a.common_prefix_len(&a.clone()));
+    assert_eq!(0, a.common_suffix_len(&IndexedSeq::new()));
+}
+
+#[test]
+fn ft_priority() {
+    let mut t : PrioritySeq<i32> = vec![3, 1, 4, 1, 5, 9, 2, 6, 5, 3].into_iter().collect();
+    assert_eq!(Some(&9), t.max());
+    assert_eq!(Some(9), t.pop_max());
+    assert_eq!(Some(6), t.pop_max());
+    assert_eq!(Some(5), t.pop_max());
+    // the remaining elements keep their order
+    assert_eq!(vec![3, 1, 4, 1, 2, 5, 3], t.iter().collect::<Vec<_>>());
+    assert_eq!(Some(&3), t.front());
+    assert_eq!(Some(&3), t.back());
+}
+
+#[test]
+fn ft_custom_measure() {
+    #[derive(Clone, Debug)]
+    struct Sum(i64);
+    impl Measure<i64> for Sum {
+        fn zero() -> Sum { Sum(0) }
+        fn of(x: &i64) -> Sum { Sum(*x) }
+        fn combine(&self, other: &Sum) -> Sum { Sum(self.0 + other.0) }
+    }
+
+    let t : FingerTree<i64, Sum> = (1..101).collect();
+    assert_eq!(5050, t.measure().0);
+    // the first element at which the running sum exceeds 100
+    assert_eq!(Some(&14), t.find(|sum| sum.0 > 100));
+    assert_eq!(Some(91), t.find_with_measure(|sum| sum.0 > 100).map(|(_, sum)| sum.0));
+    let (l, r) = t.split(|sum| sum.0 > 100);
+    assert_eq!(91, l.measure().0);
+    assert_eq!(Some(&14), r.front());
+}
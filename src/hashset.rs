@@ -0,0 +1,700 @@
+use refs::*;
+use std::borrow::Borrow;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{Iterator, FromIterator};
+use std::slice;
+
+use stats::{MemoryStats, NodeVisitor, vec_bytes};
+use show::Disp;
+
+// A hash array mapped trie (Bagwell, "Ideal Hash Trees"): each level of
+// branches consumes `BITS` bits of an element's hash.
+const BITS : u32 = 5;
+const MASK : u64 = (1 << BITS) - 1;
+
+// `DefaultHasher::new` always uses the same keys, so that sets built
+// independently of each other agree on where an element goes.
+fn hash_of<T: Hash+?Sized>(x: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    x.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn frag(hash: u64, shift: u32) -> u32 {
+    ((hash >> shift) & MASK) as u32
+}
+
+// The position of the child for `bit` in a branch's children.
+fn index(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+fn same<T: Clone>(a: &Ref<Node<T>>, b: &Ref<Node<T>>) -> bool {
+    Rc::ptr_eq(&a._ptr, &b._ptr)
+}
+
+#[derive(Clone, Debug)]
+enum Node<T: Clone> {
+    // one or more elements with the same hash
+    Leaf { hash: u64, elts: Vec<T> },
+    Branch { bitmap: u32, size: usize, children: Vec<Ref<Node<T>>> },
+}
+
+impl <T: Hash+Eq+Clone> Node<T> {
+    fn size(&self) -> usize {
+        match *self {
+            Node::Leaf { ref elts, .. }  => elts.len(),
+            Node::Branch { size, .. }    => size
+        }
+    }
+
+    fn get<Q: Eq+?Sized>(&self, hash: u64, shift: u32, x: &Q) -> Option<&T> where T: Borrow<Q> {
+        match *self {
+            Node::Leaf { hash: h, ref elts } => {
+                if h == hash { elts.iter().find(|y| (*y).borrow() == x) } else { None }
+            }
+            Node::Branch { bitmap, ref children, .. } => {
+                let bit = 1 << frag(hash, shift);
+                if bitmap & bit == 0 {
+                    return None
+                }
+                children[index(bitmap, bit)].get(hash, shift + BITS, x)
+            }
+        }
+    }
+
+    fn contains<Q: Eq+?Sized>(&self, hash: u64, shift: u32, x: &Q) -> bool where T: Borrow<Q> {
+        self.get(hash, shift, x).is_some()
+    }
+}
+
+fn leaf<T: Hash+Eq+Clone>(hash: u64, elts: Vec<T>) -> Option<Ref<Node<T>>> {
+    if elts.is_empty() {
+        None
+    } else {
+        Some(Ref::new(Node::Leaf { hash: hash, elts: elts }))
+    }
+}
+
+fn branch<T: Hash+Eq+Clone>(bitmap: u32, children: Vec<Ref<Node<T>>>) -> Ref<Node<T>> {
+    let size = children.iter().map(|c| c.size()).sum();
+    Ref::new(Node::Branch { bitmap: bitmap, size: size, children: children })
+}
+
+// Builds a branch from `(fragment, child)` pairs in increasing order of
+// fragments. Branches left without children disappear, and a branch with a
+// single leaf is replaced by the leaf, so that every branch holds at least
+// two different hashes.
+fn collect_branch<T: Hash+Eq+Clone>(entries: Vec<(u32, Ref<Node<T>>)>) -> Option<Ref<Node<T>>> {
+    if entries.len() == 1 {
+        if let Node::Leaf { .. } = *entries[0].1 {
+            return Some(entries[0].1.clone())
+        }
+    }
+    if entries.is_empty() {
+        return None
+    }
+    let bitmap = entries.iter().fold(0, |bitmap, &(f, _)| bitmap | 1 << f);
+    Some(branch(bitmap, entries.into_iter().map(|(_, c)| c).collect()))
+}
+
+fn entries<T: Hash+Eq+Clone>(bitmap: u32, children: &[Ref<Node<T>>]) -> Vec<(u32, Ref<Node<T>>)> {
+    (0..32).filter(|f| bitmap & 1 << f != 0).zip(children.iter().cloned()).collect()
+}
+
+// Joins two leaves with different hashes into a branch at level `shift`.
+fn join<T: Hash+Eq+Clone>(shift: u32, a: Ref<Node<T>>, ha: u64, b: Ref<Node<T>>, hb: u64) -> Ref<Node<T>> {
+    let (fa, fb) = (frag(ha, shift), frag(hb, shift));
+    if fa == fb {
+        branch(1 << fa, vec![join(shift + BITS, a, ha, b, hb)])
+    } else if fa < fb {
+        branch(1 << fa | 1 << fb, vec![a, b])
+    } else {
+        branch(1 << fa | 1 << fb, vec![b, a])
+    }
+}
+
+// `None` if `x` already is in `node`.
+fn insert<T: Hash+Eq+Clone>(node: &Ref<Node<T>>, hash: u64, shift: u32, x: T) -> Option<Ref<Node<T>>> {
+    match **node {
+        Node::Leaf { hash: h, ref elts } => {
+            if h != hash {
+                let new_leaf = Ref::new(Node::Leaf { hash: hash, elts: vec![x] });
+                return Some(join(shift, node.clone(), h, new_leaf, hash))
+            }
+            if elts.contains(&x) {
+                return None
+            }
+            let mut elts = elts.clone();
+            elts.push(x);
+            leaf(hash, elts)
+        }
+        Node::Branch { bitmap, ref children, .. } => {
+            let bit = 1 << frag(hash, shift);
+            let pos = index(bitmap, bit);
+            if bitmap & bit != 0 {
+                let child = match insert(&children[pos], hash, shift + BITS, x) {
+                    Some(child) => child,
+                    None        => return None
+                };
+                let mut children = children.clone();
+                children[pos] = child;
+                Some(branch(bitmap, children))
+            } else {
+                let mut children = children.clone();
+                children.insert(pos, Ref::new(Node::Leaf { hash: hash, elts: vec![x] }));
+                Some(branch(bitmap | bit, children))
+            }
+        }
+    }
+}
+
+// `None` if `x` is not in `node`, `Some(None)` if the node became empty.
+fn remove<T, Q>(node: &Ref<Node<T>>, hash: u64, shift: u32, x: &Q) -> Option<Option<Ref<Node<T>>>>
+    where T: Hash+Eq+Clone+Borrow<Q>, Q: Eq+?Sized
+{
+    match **node {
+        Node::Leaf { hash: h, ref elts } => {
+            if h != hash || !elts.iter().any(|y| y.borrow() == x) {
+                return None
+            }
+            Some(leaf(hash, elts.iter().filter(|y| (*y).borrow() != x).cloned().collect()))
+        }
+        Node::Branch { bitmap, ref children, .. } => {
+            let f = frag(hash, shift);
+            let bit = 1 << f;
+            if bitmap & bit == 0 {
+                return None
+            }
+            let child = match remove(&children[index(bitmap, bit)], hash, shift + BITS, x) {
+                Some(child) => child,
+                None        => return None
+            };
+            let mut entries : Vec<_> = entries(bitmap, children).into_iter().filter(|e| e.0 != f).collect();
+            if let Some(child) = child {
+                let pos = index(bitmap, bit);
+                entries.insert(pos, (f, child));
+            }
+            Some(collect_branch(entries))
+        }
+    }
+}
+
+// The algebra below stops descending as soon as both sides point to the same
+// node, so that combining versions of one set only visits the parts in which
+// they differ.
+
+fn union<T: Hash+Eq+Clone>(a: &Ref<Node<T>>, b: &Ref<Node<T>>, shift: u32) -> Ref<Node<T>> {
+    if same(a, b) {
+        return a.clone()
+    }
+    match (&**a, &**b) {
+        (&Node::Leaf { hash, ref elts }, _) => {
+            let mut ret = b.clone();
+            for x in elts {
+                if let Some(node) = insert(&ret, hash, shift, x.clone()) {
+                    ret = node;
+                }
+            }
+            ret
+        }
+        (_, &Node::Leaf { hash, ref elts }) => {
+            let mut ret = a.clone();
+            for x in elts {
+                if let Some(node) = insert(&ret, hash, shift, x.clone()) {
+                    ret = node;
+                }
+            }
+            ret
+        }
+        (&Node::Branch { bitmap: ba, children: ref ca, .. },
+         &Node::Branch { bitmap: bb, children: ref cb, .. }) => {
+            let mut children = Vec::with_capacity((ba | bb).count_ones() as usize);
+            let mut unchanged = ba | bb == ba;
+            for f in 0..32 {
+                let bit = 1 << f;
+                match (ba & bit != 0, bb & bit != 0) {
+                    (true, true) => {
+                        let ca = &ca[index(ba, bit)];
+                        let c = union(ca, &cb[index(bb, bit)], shift + BITS);
+                        unchanged = unchanged && same(&c, ca);
+                        children.push(c);
+                    }
+                    (true, false) => children.push(ca[index(ba, bit)].clone()),
+                    (false, true) => children.push(cb[index(bb, bit)].clone()),
+                    (false, false) => {}
+                }
+            }
+            if unchanged {
+                a.clone()
+            } else {
+                branch(ba | bb, children)
+            }
+        }
+    }
+}
+
+fn intersection<T: Hash+Eq+Clone>(a: &Ref<Node<T>>, b: &Ref<Node<T>>, shift: u32) -> Option<Ref<Node<T>>> {
+    if same(a, b) {
+        return Some(a.clone())
+    }
+    match (&**a, &**b) {
+        (&Node::Leaf { hash, ref elts }, b) => {
+            leaf(hash, elts.iter().filter(|x| b.contains(hash, shift, x)).cloned().collect())
+        }
+        (a, &Node::Leaf { hash, ref elts }) => {
+            leaf(hash, elts.iter().filter(|x| a.contains(hash, shift, x)).cloned().collect())
+        }
+        (&Node::Branch { bitmap: ba, children: ref ca, .. },
+         &Node::Branch { bitmap: bb, children: ref cb, .. }) => {
+            let mut entries = vec![];
+            for f in 0..32 {
+                let bit = 1 << f;
+                if ba & bit != 0 && bb & bit != 0 {
+                    if let Some(c) = intersection(&ca[index(ba, bit)], &cb[index(bb, bit)], shift + BITS) {
+                        entries.push((f, c));
+                    }
+                }
+            }
+            collect_branch(entries)
+        }
+    }
+}
+
+fn difference<T: Hash+Eq+Clone>(a: &Ref<Node<T>>, b: &Ref<Node<T>>, shift: u32) -> Option<Ref<Node<T>>> {
+    if same(a, b) {
+        return None
+    }
+    match (&**a, &**b) {
+        (&Node::Leaf { hash, ref elts }, b) => {
+            leaf(hash, elts.iter().filter(|x| !b.contains(hash, shift, x)).cloned().collect())
+        }
+        (_, &Node::Leaf { hash, ref elts }) => {
+            let mut ret = Some(a.clone());
+            for x in elts {
+                let removed = match ret {
+                    Some(ref node) => remove(node, hash, shift, x),
+                    None           => break
+                };
+                if let Some(node) = removed {
+                    ret = node;
+                }
+            }
+            ret
+        }
+        (&Node::Branch { bitmap: ba, children: ref ca, .. },
+         &Node::Branch { bitmap: bb, children: ref cb, .. }) => {
+            let mut entries = vec![];
+            for f in 0..32 {
+                let bit = 1 << f;
+                if ba & bit == 0 {
+                    continue
+                }
+                let ca = &ca[index(ba, bit)];
+                if bb & bit == 0 {
+                    entries.push((f, ca.clone()));
+                } else if let Some(c) = difference(ca, &cb[index(bb, bit)], shift + BITS) {
+                    entries.push((f, c));
+                }
+            }
+            collect_branch(entries)
+        }
+    }
+}
+
+fn is_subset<T: Hash+Eq+Clone>(a: &Ref<Node<T>>, b: &Ref<Node<T>>, shift: u32) -> bool {
+    if same(a, b) {
+        return true
+    }
+    if a.size() > b.size() {
+        return false
+    }
+    match (&**a, &**b) {
+        (&Node::Branch { bitmap: ba, children: ref ca, .. },
+         &Node::Branch { bitmap: bb, children: ref cb, .. }) => {
+            ba & !bb == 0 && (0..32).map(|f| 1 << f).filter(|bit| ba & bit != 0).all(|bit| {
+                is_subset(&ca[index(ba, bit)], &cb[index(bb, bit)], shift + BITS)
+            })
+        }
+        (a, b) => Iter::new(Some(a)).all(|x| b.contains(hash_of(x), shift, x))
+    }
+}
+
+/// A persistent hash set. Membership tests and updates take O(log n), clones
+/// are O(1), and the set operations skip over the parts that two sets share,
+/// so that combining versions of one set is cheap.
+#[derive(Clone)]
+pub struct HashSet<T: Hash+Eq+Clone> {
+    root : Option<Ref<Node<T>>>,
+}
+
+impl <T: Hash+Eq+Clone> HashSet<T> {
+    pub fn new() -> HashSet<T> {
+        HashSet { root: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |root| root.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns false if `x` already was in the set.
+    pub fn insert(&mut self, x: T) -> bool {
+        let hash = hash_of(&x);
+        let root = match self.root {
+            None           => Ref::new(Node::Leaf { hash: hash, elts: vec![x] }),
+            Some(ref root) => match insert(root, hash, 0, x) {
+                Some(root) => root,
+                None       => return false
+            }
+        };
+        self.root = Some(root);
+        true
+    }
+
+    /// Returns false if `x` was not in the set.
+    pub fn remove<Q: Hash+Eq+?Sized>(&mut self, x: &Q) -> bool where T: Borrow<Q> {
+        let root = match self.root {
+            None           => return false,
+            Some(ref root) => match remove(root, hash_of(x), 0, x) {
+                Some(root) => root,
+                None       => return false
+            }
+        };
+        self.root = root;
+        true
+    }
+
+    pub fn contains<Q: Hash+Eq+?Sized>(&self, x: &Q) -> bool where T: Borrow<Q> {
+        self.get(x).is_some()
+    }
+
+    /// The element of the set that is equal to `x`.
+    pub fn get<Q: Hash+Eq+?Sized>(&self, x: &Q) -> Option<&T> where T: Borrow<Q> {
+        self.root.as_ref().and_then(|root| root.get(hash_of(x), 0, x))
+    }
+
+    pub fn union(&self, other: &HashSet<T>) -> HashSet<T> {
+        match (&self.root, &other.root) {
+            (&Some(ref a), &Some(ref b)) => HashSet { root: Some(union(a, b, 0)) },
+            (&None, _)                   => other.clone(),
+            (_, &None)                   => self.clone()
+        }
+    }
+
+    pub fn intersection(&self, other: &HashSet<T>) -> HashSet<T> {
+        match (&self.root, &other.root) {
+            (&Some(ref a), &Some(ref b)) => HashSet { root: intersection(a, b, 0) },
+            _                            => HashSet::new()
+        }
+    }
+
+    /// The elements of `self` that are not in `other`.
+    pub fn difference(&self, other: &HashSet<T>) -> HashSet<T> {
+        match (&self.root, &other.root) {
+            (&Some(ref a), &Some(ref b)) => HashSet { root: difference(a, b, 0) },
+            _                            => self.clone()
+        }
+    }
+
+    /// The elements that are in exactly one of the sets.
+    pub fn symmetric_difference(&self, other: &HashSet<T>) -> HashSet<T> {
+        self.difference(other).union(&other.difference(self))
+    }
+
+    pub fn is_subset(&self, other: &HashSet<T>) -> bool {
+        match (&self.root, &other.root) {
+            (&Some(ref a), &Some(ref b)) => is_subset(a, b, 0),
+            (&None, _)                   => true,
+            (_, &None)                   => false
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(self.root.as_ref().map(|root| &**root))
+    }
+}
+
+impl <T: Hash+Eq+Clone> PartialEq for HashSet<T> {
+    fn eq(&self, other: &HashSet<T>) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+}
+
+impl <T: Hash+Eq+Clone> Eq for HashSet<T> {}
+
+/// `{1, 2, 3}` in hash order, or with `{:#?}` the nodes of the trie.
+impl <T: Hash+Eq+Clone+fmt::Debug> fmt::Debug for HashSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("HashSet").field("root", &self.root).finish()
+        }
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl <T: Hash+Eq+Clone+fmt::Display> fmt::Display for HashSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+impl <T: Hash+Eq+Clone> FromIterator<T> for HashSet<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> HashSet<T> {
+        let mut ret = HashSet::new();
+        for x in iter {
+            ret.insert(x);
+        }
+        ret
+    }
+}
+
+fn visit_node<T: Clone>(node: &Ref<Node<T>>, v: &mut NodeVisitor) {
+    match **node {
+        Node::Leaf { ref elts, .. } => {
+            v.node_ref(node, vec_bytes(elts));
+        }
+        Node::Branch { ref children, .. } => {
+            if v.node_ref(node, vec_bytes(children)) {
+                for child in children {
+                    visit_node(child, v);
+                }
+            }
+        }
+    }
+}
+
+impl <T: Hash+Eq+Clone> MemoryStats for HashSet<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        if let Some(ref root) = self.root {
+            visit_node(root, v)
+        }
+    }
+}
+
+pub struct Iter<'a, T: Clone+'a> {
+    stack : Vec<&'a Node<T>>,
+    elts  : slice::Iter<'a, T>,
+}
+
+impl <'a, T: Clone> Iter<'a, T> {
+    fn new(root: Option<&'a Node<T>>) -> Iter<'a, T> {
+        let empty : &'a [T] = &[];
+        Iter { stack: root.into_iter().collect(), elts: empty.iter() }
+    }
+}
+
+impl <'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(x) = self.elts.next() {
+                return Some(x)
+            }
+            match self.stack.pop() {
+                None => return None,
+                Some(&Node::Leaf { ref elts, .. }) => self.elts = elts.iter(),
+                Some(&Node::Branch { ref children, .. }) => {
+                    for c in children.iter().rev() {
+                        self.stack.push(&**c);
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+#[test]
+fn hs_insert_remove() {
+    let mut s = HashSet::new();
+    assert!(s.is_empty());
+    for i in 0..1000 {
+        assert!(s.insert(i));
+    }
+    assert!(!s.insert(500));
+    assert_eq!(1000, s.len());
+    for i in 0..1000 {
+        assert!(s.contains(&i));
+    }
+    assert!(!s.contains(&1000));
+
+    let snapshot = s.clone();
+    for i in 0..1000 {
+        if i % 3 == 0 {
+            assert!(s.remove(&i));
+        }
+    }
+    assert!(!s.remove(&0));
+    assert_eq!(666, s.len());
+    assert_eq!(1000, snapshot.len());
+    assert!(snapshot.contains(&0));
+    assert!(!s.contains(&0));
+
+    let mut elts : Vec<_> = s.iter().cloned().collect();
+    elts.sort();
+    assert_eq!((0..1000).filter(|i| i % 3 != 0).collect::<Vec<_>>(), elts);
+}
+
+#[test]
+fn hs_algebra() {
+    let a : HashSet<i32> = (0..100).collect();
+    let b : HashSet<i32> = (50..150).collect();
+
+    assert_eq!((0..150).collect::<HashSet<_>>(), a.union(&b));
+    assert_eq!((50..100).collect::<HashSet<_>>(), a.intersection(&b));
+    assert_eq!((0..50).collect::<HashSet<_>>(), a.difference(&b));
+    assert_eq!((0..50).chain(100..150).collect::<HashSet<_>>(), a.symmetric_difference(&b));
+    assert!(a.intersection(&b).is_subset(&a));
+    assert!(a.is_subset(&a.union(&b)));
+    assert!(!a.is_subset(&b));
+    assert!(HashSet::new().is_subset(&a));
+    assert_eq!(0, a.difference(&a).len());
+}
+
+#[test]
+fn hs_union_shares() {
+    let base : HashSet<i32> = (0..10000).collect();
+    let mut a = base.clone();
+    a.insert(-1);
+    let mut b = base.clone();
+    b.remove(&5);
+
+    // a union of a set with its own version reuses its nodes
+    let u = a.union(&base);
+    assert!(same(u.root.as_ref().unwrap(), a.root.as_ref().unwrap()));
+    assert_eq!(10001, a.union(&b).len());
+    assert_eq!(9999, a.intersection(&b).len());
+}
+
+#[test]
+fn hs_collisions() {
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Colliding(i32);
+    impl Hash for Colliding {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 4).hash(state)
+        }
+    }
+
+    let mut s = HashSet::new();
+    for i in 0..20 {
+        s.insert(Colliding(i));
+    }
+    assert_eq!(20, s.len());
+    assert!(s.contains(&Colliding(13)));
+    assert!(s.remove(&Colliding(13)));
+    assert!(!s.contains(&Colliding(13)));
+    let evens : HashSet<_> = (0..10).map(|i| Colliding(2 * i)).collect();
+    assert_eq!(10, s.intersection(&evens).len());
+    assert_eq!(9, s.difference(&evens).len());
+}
+
+#[test]
+fn hs_fmt() {
+    let mut s = HashSet::new();
+    assert_eq!("{}", format!("{:?}", s));
+    s.insert("a");
+    assert_eq!("{\"a\"}", format!("{:?}", s));
+    assert_eq!("{a}", format!("{}", s));
+    let s : HashSet<i32> = (0..3).collect();
+    let shown = format!("{:?}", s);
+    for i in 0..3 {
+        assert!(shown.contains(&i.to_string()));
+    }
+    assert!(format!("{:#?}", s).starts_with("HashSet {"));
+}
+
+#[cfg(test)]
+mod bench {
+    use test;
+    use test::Bencher;
+    use super::HashSet;
+    use std::collections::HashSet as StdHashSet;
+
+    #[bench]
+    fn hs_insert(b: &mut Bencher) {
+        let mut s = HashSet::new();
+        let mut i = 0;
+        b.iter(
+            || {
+                i += 1;
+                test::black_box(s.insert(i));
+            }
+        );
+    }
+
+    #[bench]
+    fn std_hs_insert(b: &mut Bencher) {
+        let mut s = StdHashSet::new();
+        let mut i = 0;
+        b.iter(
+            || {
+                i += 1;
+                test::black_box(s.insert(i));
+            }
+        );
+    }
+
+    #[bench]
+    fn hs_contains(b: &mut Bencher) {
+        let s : HashSet<i32> = (0..100000).collect();
+        let mut i = 0;
+        b.iter(
+            || {
+                i = (i + 7919) % 200000;
+                test::black_box(s.contains(&i));
+            }
+        );
+    }
+
+    #[bench]
+    fn std_hs_contains(b: &mut Bencher) {
+        let s : StdHashSet<i32> = (0..100000).collect();
+        let mut i = 0;
+        b.iter(
+            || {
+                i = (i + 7919) % 200000;
+                test::black_box(s.contains(&i));
+            }
+        );
+    }
+
+    #[bench]
+    fn hs_union_overlapping(b: &mut Bencher) {
+        let base : HashSet<i32> = (0..100000).collect();
+        let mut s1 = base.clone();
+        let mut s2 = base.clone();
+        for i in 0..10 {
+            s1.insert(-i);
+            s2.insert(100000 + i);
+        }
+        b.iter(
+            || {
+                test::black_box(s1.union(&s2));
+            }
+        );
+    }
+
+    #[bench]
+    fn std_hs_union_overlapping(b: &mut Bencher) {
+        let base : StdHashSet<i32> = (0..100000).collect();
+        let mut s1 = base.clone();
+        let mut s2 = base.clone();
+        for i in 0..10 {
+            s1.insert(-i);
+            s2.insert(100000 + i);
+        }
+        b.iter(
+            || {
+                test::black_box(s1.union(&s2).cloned().collect::<StdHashSet<i32>>());
+            }
+        );
+    }
+
+}
@@ -1,18 +1,46 @@
-use giftr::refs::*;
-//use giftr::refs::functional::Ref as Ref;
-use giftr::refs::imperative::Ref as Ref;
-use std::iter::Iterator;
+use refs::*;
+use archive::{Archivable, Archive, ArchiveReader, ArchiveError, Link};
+use stats::{MemoryStats, NodeVisitor};
+use dot::{ToDot, Graph};
+use show::{Disp, Dump};
+use merge::{Merge3, Conflict};
+//use giftr::refs::imperative::Ref as Ref;
+use std::iter::{Iterator, FromIterator};
+use std::collections::{VecDeque, HashMap, BinaryHeap, BTreeMap};
 
-use std::cmp::Ord;
+use std::cmp::{Ord, Reverse, max};
+use std::mem::replace;
+use std::fmt;
+use std::fmt::Debug;
 
-#[derive(Clone,Debug)]
+#[derive(Clone)]
 pub struct Heap<T: Ord+Clone> {
-    cell : Ref<HeapCell<T>>,
+    cell  : Ref<HeapCell<T>>,
+    // shared by all leaves of this heap, so that inserting does not allocate
+    // two fresh empty cells per element
+    empty : Ref<HeapCell<T>>,
 }
 
 impl <T: Ord+Clone> Heap<T> {
     pub fn new() -> Heap<T> {
-        Heap { cell: Ref::new(HeapCell::new()) }
+        let empty = Ref::new(HeapCell::new());
+        Heap { cell: empty.clone(), empty: empty }
+    }
+
+    /// Builds a heap in O(n) by merging singleton heaps pairwise, round after
+    /// round, instead of inserting the elements one by one.
+    pub fn from_vec(xs: Vec<T>) -> Heap<T> {
+        let empty = Ref::new(HeapCell::new());
+        let mut queue : VecDeque<HeapCell<T>> = xs.into_iter()
+            .map(|x| HeapCell::singleton(x, &empty))
+            .collect();
+        while queue.len() > 1 {
+            let h1 = queue.pop_front().unwrap();
+            let h2 = queue.pop_front().unwrap();
+            queue.push_back(h1.merge(h2));
+        }
+        let cell = queue.pop_front().unwrap_or(HeapCell::Empty);
+        Heap { cell: Ref::new(cell), empty: empty }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -20,7 +48,14 @@ impl <T: Ord+Clone> Heap<T> {
     }
 
     pub fn insert(&mut self, x: T) {
-        self.cell.apply(|cell| cell.insert(x))
+        let empty = &self.empty;
+        self.cell.apply(|cell| cell.merge(HeapCell::singleton(x, empty)))
+    }
+
+    pub fn merge(&mut self, mut other: Heap<T>) {
+        let empty = other.empty.clone();
+        let other = replace(&mut other.cell, empty).consume();
+        self.cell.apply(|cell| cell.merge(other))
     }
 
     pub fn pop_min(&mut self) -> Option<T> {
@@ -46,6 +81,22 @@ impl <T: Ord+Clone> Heap<T> {
 
 }
 
+// Dropping a cell drops its children from within, so the long left spines
+// made by inserting in decreasing order would overflow the stack. The cells
+// the heap owns alone are taken apart with a stack of our own instead.
+impl <T: Ord+Clone> Drop for Heap<T> {
+    fn drop(&mut self) {
+        let empty = self.empty.clone();
+        let mut stack = vec![replace(&mut self.cell, empty)];
+        while let Some(cell) = stack.pop() {
+            if let Ok(HeapCell::Node { left, right, .. }) = Rc::try_unwrap(cell._ptr) {
+                stack.push(left);
+                stack.push(right);
+            }
+        }
+    }
+}
+
 pub struct Iter<T: Ord+Clone> {
     heap: Heap<T>,
 }
@@ -57,7 +108,346 @@ impl <T: Ord+Clone> Iterator for Iter<T> {
     }
 }
 
-#[derive(Clone, Debug)]
+impl <T: Ord+Clone> FromIterator<T> for Heap<T> {
+    fn from_iter<I: IntoIterator<Item=T>>(iter: I) -> Heap<T> {
+        Heap::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl <T: Ord+Clone> Heap<T> {
+    // The number of elements, counted with a stack of our own since the left
+    // spine can be as long as the heap.
+    fn count(&self) -> usize {
+        let mut ret = 0;
+        let mut stack = vec![&self.cell];
+        while let Some(cell) = stack.pop() {
+            if let HeapCell::Node { ref left, ref right, .. } = **cell {
+                ret += 1;
+                stack.push(left);
+                stack.push(right);
+            }
+        }
+        ret
+    }
+}
+
+/// `Heap{min: 1, len: 5}`, or with `{:#?}` the cells with their ranks and
+/// strong counts.
+impl <T: Ord+Clone+Debug> Debug for Heap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return match *self.cell {
+                HeapCell::Empty                => write!(f, "Heap{{len: 0}}"),
+                HeapCell::Node { ref elt, .. } => write!(f, "Heap{{min: {:?}, len: {}}}", elt, self.count())
+            }
+        }
+        let mut d = Dump::new(f, "Heap")?;
+        let mut stack = vec![&self.cell];
+        while let Some(cell) = stack.pop() {
+            if !d.node(cell)? {
+                continue
+            }
+            match **cell {
+                HeapCell::Empty => d.line(format_args!("empty"))?,
+                HeapCell::Node { rank, ref elt, ref left, ref right } => {
+                    let (l, r) = (d.id(left), d.id(right));
+                    d.line(format_args!("{:?}, rank {}, l #{}, r #{}", elt, rank, l, r))?;
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        d.finish()
+    }
+}
+
+/// The elements in increasing order, `Heap[1, 2, 3]`.
+impl <T: Ord+Clone+fmt::Display> fmt::Display for Heap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Heap")?;
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+// A cell and not the heap below it, which would take a deep recursion.
+impl <T: Ord+Clone+Debug> Debug for HeapCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeapCell::Empty                      => write!(f, "Empty"),
+            HeapCell::Node { rank, ref elt, .. } => write!(f, "Node{{rank: {}, elt: {:?}, ..}}", rank, elt)
+        }
+    }
+}
+
+// The cells both sides of `Heap::delta` have reached and not gone into yet,
+// smallest element first.
+struct Frontier<'a, T: Ord+Clone+'a> {
+    cells   : Vec<(&'a Ref<HeapCell<T>>, usize)>,
+    queue   : BinaryHeap<(Reverse<&'a T>, Reverse<usize>)>,
+    // how many times each side has reached a cell, by its address
+    reached : [HashMap<usize, usize>; 2],
+}
+
+impl <'a, T: Ord+Clone> Frontier<'a, T> {
+    fn reach(&mut self, cell: &'a Ref<HeapCell<T>>, side: usize) {
+        if let HeapCell::Node { ref elt, .. } = **cell {
+            *self.reached[side].entry(addr(cell)).or_insert(0) += 1;
+            self.queue.push((Reverse(elt), Reverse(self.cells.len())));
+            self.cells.push((cell, side));
+        }
+    }
+
+    // Takes the cell off the side's frontier, false if it was not there.
+    fn leave(&mut self, cell: usize, side: usize) -> bool {
+        match self.reached[side].get_mut(&cell) {
+            Some(n) if *n > 0 => { *n -= 1; true }
+            _                 => false
+        }
+    }
+}
+
+fn addr<T: Ord+Clone>(cell: &Ref<HeapCell<T>>) -> usize {
+    &**cell as *const HeapCell<T> as usize
+}
+
+impl <T: Ord+Clone> Heap<T> {
+    // How many more copies of each element `self` holds than `base`. The two
+    // heaps are walked together, smallest element first, and a cell reached
+    // from both sides roots a subtree they share, which holds as many of each
+    // element and is skipped. The cells above a shared one hold no larger
+    // elements, so both sides reach it before either goes into it; only ties
+    // with equal elements can make the walk miss it, and go in for nothing.
+    // The cost grows with the cells the heaps do not share, not with their size.
+    fn delta<'a>(&'a self, base: &'a Heap<T>) -> BTreeMap<&'a T, isize> {
+        let mut ret = BTreeMap::new();
+        let mut frontier = Frontier {
+            cells   : vec![],
+            queue   : BinaryHeap::new(),
+            reached : [HashMap::new(), HashMap::new()],
+        };
+        frontier.reach(&self.cell, 0);
+        frontier.reach(&base.cell, 1);
+        while let Some((_, Reverse(i))) = frontier.queue.pop() {
+            let (cell, side) = frontier.cells[i];
+            let at = addr(cell);
+            // already taken off as part of a shared pair
+            if !frontier.leave(at, side) {
+                continue
+            }
+            if frontier.leave(at, 1 - side) {
+                continue
+            }
+            if let HeapCell::Node { ref elt, ref left, ref right, .. } = **cell {
+                *ret.entry(elt).or_insert(0) += if side == 0 { 1 } else { -1 };
+                frontier.reach(left, side);
+                frontier.reach(right, side);
+            }
+        }
+        ret
+    }
+}
+
+/// Merges the heaps as multisets: each side adds and removes copies of
+/// elements, and copies of an element removed on both sides are removed once.
+/// This never conflicts.
+///
+/// Only the cells the versions do not share are compared, and the copies
+/// `theirs` removed are popped off the front of `ours`: the cost grows with
+/// the changes, and with the elements smaller than the removed ones, rather
+/// than with the size of the heaps.
+impl <T: Ord+Clone> Merge3 for Heap<T> {
+    type Elt = T;
+
+    fn merge3(base: &Heap<T>, ours: &Heap<T>, theirs: &Heap<T>) -> Result<Heap<T>, Vec<Conflict<T>>> {
+        let same = |a: &Heap<T>, b: &Heap<T>| Rc::ptr_eq(&a.cell._ptr, &b.cell._ptr);
+        if same(base, ours) {
+            return Ok(theirs.clone())
+        }
+        if same(base, theirs) || same(ours, theirs) {
+            return Ok(ours.clone())
+        }
+        let (ours_delta, theirs_delta) = (ours.delta(base), theirs.delta(base));
+        let mut ret = ours.clone();
+        let mut removed = BTreeMap::new();
+        for (&x, &n) in &theirs_delta {
+            let ours_removed = max(0, -ours_delta.get(x).cloned().unwrap_or(0));
+            let added = max(0, n) - max(0, -n - ours_removed);
+            for _ in 0..added {
+                ret.insert(x.clone());
+            }
+            if added < 0 {
+                removed.insert(x.clone(), -added);
+            }
+        }
+        let mut kept = vec![];
+        while !removed.is_empty() {
+            let x = match ret.pop_min() {
+                Some(x) => x,
+                None    => break
+            };
+            let gone = match removed.get_mut(&x) {
+                Some(n) => { *n -= 1; *n == 0 }
+                None    => { kept.push(x); continue }
+            };
+            if gone {
+                removed.remove(&x);
+            }
+        }
+        for x in kept {
+            ret.insert(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Ord+Clone> MemoryStats for Heap<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        visit_cell(&self.cell, v);
+        visit_cell(&self.empty, v)
+    }
+}
+
+fn visit_cell<T: Ord+Clone>(cell: &Ref<HeapCell<T>>, v: &mut NodeVisitor) {
+    let mut todo = vec![cell];
+    while let Some(cell) = todo.pop() {
+        if !v.node_ref(cell, 0) {
+            continue
+        }
+        if let HeapCell::Node { ref left, ref right, .. } = **cell {
+            todo.push(right);
+            todo.push(left);
+        }
+    }
+}
+
+impl <T: Ord+Clone+Debug> ToDot for Heap<T> {
+    fn to_dot(&self, g: &mut Graph) -> String {
+        dot_cell(&self.cell, g)
+    }
+}
+
+// Drawn with a stack of our own, in the order a recursive walk would take: a
+// cell, its left subtree and the edge to it, then the same on the right.
+fn dot_cell<T: Ord+Clone+Debug>(cell: &Ref<HeapCell<T>>, g: &mut Graph) -> String {
+    enum Step<'a, T: Ord+Clone+'a> {
+        Cell(&'a Ref<HeapCell<T>>),
+        Edge(String, &'static str),
+    }
+    let mut todo = vec![Step::Cell(cell)];
+    // the ids of the cells drawn whose edge from their parent is not yet
+    let mut ids = vec![];
+    while let Some(step) = todo.pop() {
+        match step {
+            Step::Cell(cell) => match **cell {
+                HeapCell::Empty => ids.push(g.node_ref(cell, &["empty".to_string()]).0),
+                HeapCell::Node { rank, ref elt, ref left, ref right } => {
+                    let (id, new) = g.node_ref(cell, &[format!("{:?}", elt), format!("rank {}", rank)]);
+                    if new {
+                        todo.push(Step::Edge(id.clone(), "r"));
+                        todo.push(Step::Cell(right));
+                        todo.push(Step::Edge(id.clone(), "l"));
+                        todo.push(Step::Cell(left));
+                    }
+                    ids.push(id);
+                }
+            },
+            Step::Edge(from, label) => {
+                let to = ids.pop().unwrap();
+                g.edge(&from, &to, label);
+            }
+        }
+    }
+    ids.pop().unwrap()
+}
+
+// Cells are written depth-first, each once; the empty cell shared by the
+// leaves is written once and referred to after that.
+impl <T: Ord+Clone+Archivable+'static> Archivable for Heap<T> {
+    fn archive(&self, a: &mut Archive) {
+        archive_cell(&self.cell, a);
+        archive_cell(&self.empty, a)
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<Heap<T>, ArchiveError> {
+        let cell = unarchive_cell(r)?;
+        let empty = unarchive_cell(r)?;
+        if !empty.is_empty() {
+            return Err(ArchiveError::Invalid("heap: bad empty cell"))
+        }
+        Ok(Heap { cell: cell, empty: empty })
+    }
+}
+
+// The left spine can be as long as the heap, so both walks keep a stack of
+// their own.
+fn archive_cell<T: Ord+Clone+Archivable+'static>(cell: &Ref<HeapCell<T>>, a: &mut Archive) {
+    let mut stack = vec![cell];
+    while let Some(cell) = stack.pop() {
+        if !a.begin_node(cell) {
+            continue
+        }
+        match **cell {
+            HeapCell::Empty => a.write_varint(0),
+            HeapCell::Node { rank, ref elt, ref left, ref right } => {
+                a.write_varint(1);
+                rank.archive(a);
+                elt.archive(a);
+                stack.push(right);
+                stack.push(left);
+            }
+        }
+    }
+}
+
+fn unarchive_cell<T: Ord+Clone+Archivable+'static>(r: &mut ArchiveReader) -> Result<Ref<HeapCell<T>>, ArchiveError> {
+    // the nodes whose children are being read, with the left one once it is
+    let mut pending : Vec<(u64, i32, T, Option<Ref<HeapCell<T>>>)> = vec![];
+    loop {
+        let mut done = match r.read_link()? {
+            Link::None         => return Err(ArchiveError::Invalid("heap: missing cell")),
+            Link::Shared(cell) => cell,
+            Link::New(id)      => match r.read_varint()? {
+                0 => {
+                    let cell = Ref::new(HeapCell::Empty);
+                    r.fill_node(id, &cell);
+                    cell
+                }
+                1 => {
+                    let rank = i32::unarchive(r)?;
+                    let elt = T::unarchive(r)?;
+                    pending.push((id, rank, elt, None));
+                    continue
+                }
+                _ => return Err(ArchiveError::Invalid("heap: bad cell"))
+            }
+        };
+        // hands the finished cell to its parent, building the parents whose
+        // right child it completes
+        loop {
+            match pending.pop() {
+                None => return Ok(done),
+                Some((id, rank, elt, None)) => {
+                    pending.push((id, rank, elt, Some(done)));
+                    break
+                }
+                Some((id, rank, elt, Some(left))) => {
+                    let right = done;
+                    if left.root().map_or(false, |x| *x < elt) || right.root().map_or(false, |x| *x < elt) {
+                        return Err(ArchiveError::Invalid("heap: a child is smaller than its parent"))
+                    }
+                    if left.rank() < right.rank() || rank != right.rank() + 1 {
+                        return Err(ArchiveError::Invalid("heap: wrong rank"))
+                    }
+                    let cell = Ref::new(HeapCell::Node { rank: rank, elt: elt, left: left, right: right });
+                    r.fill_node(id, &cell);
+                    done = cell;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum HeapCell<T: Ord+Clone> {
     Empty,
     Node { rank: i32, elt: T, left:  Ref<HeapCell<T>>, right: Ref<HeapCell<T>> },
@@ -75,8 +465,14 @@ impl <T: Ord+Clone> HeapCell<T> {
         }
     }
 
-    pub fn insert(self, x: T) -> Self {
-        self.merge(HeapCell::Node { rank: 1, elt: x, left: Ref::new(HeapCell::new()), right: Ref::new(HeapCell::new()) })
+    /// Inserts `x` as a singleton whose two leaves both point to `empty`.
+    pub fn insert(self, x: T, empty: &Ref<HeapCell<T>>) -> Self {
+        self.merge(HeapCell::singleton(x, empty))
+    }
+
+    /// A one-element heap whose two leaves both point to `empty`.
+    pub fn singleton(x: T, empty: &Ref<HeapCell<T>>) -> Self {
+        HeapCell::Node { rank: 1, elt: x, left: empty.clone(), right: empty.clone() }
     }
 
     fn make_node(x: T, h1: Ref<HeapCell<T>>, h2: Ref<HeapCell<T>>) -> HeapCell<T> {
@@ -115,6 +511,13 @@ impl <T: Ord+Clone> HeapCell<T> {
         }
     }
 
+    fn root(&self) -> Option<&T> {
+        match *self {
+            HeapCell::Empty              => None,
+            HeapCell::Node{ref elt, ..}  => Some(elt)
+        }
+    }
+
     pub fn pop_min(self) -> Option<(T, Self)> {
         match self {
             HeapCell::Empty => None,
@@ -150,6 +553,20 @@ fn heap_min() {
     assert_eq!(None,    h.pop_min());
 }
 
+#[test]
+fn heap_shared_leaves() {
+    let mut h : Heap<i8> = Heap::new();
+    assert!(Rc::ptr_eq(&h.cell._ptr, &h.empty._ptr));
+    h.insert(1);
+    match *h.cell {
+        HeapCell::Node { ref left, ref right, .. } => {
+            assert!(Rc::ptr_eq(&left._ptr, &h.empty._ptr));
+            assert!(Rc::ptr_eq(&right._ptr, &h.empty._ptr));
+        }
+        HeapCell::Empty => panic!("the heap is not empty")
+    }
+}
+
 #[test]
 fn heap_iter() {
     let mut h : Heap<i8> = Heap::new();
@@ -172,11 +589,206 @@ fn heap_iter() {
     }
 }
 
+#[test]
+fn heap_from_vec() {
+    let h = Heap::from_vec(vec![4, 2, 7, 1, 5, 3, 6]);
+
+    let mut i = 1;
+    for v in h.to_iter() {
+        assert_eq!(i, v);
+        i += 1;
+    }
+    assert_eq!(8, i);
+}
+
+#[test]
+fn heap_from_vec_empty() {
+    let mut h : Heap<i8> = Heap::from_vec(vec![]);
+    assert!(h.is_empty());
+    assert_eq!(None, h.pop_min());
+    h.insert(1);
+    assert_eq!(Some(1), h.pop_min());
+}
+
+#[test]
+fn heap_from_iter() {
+    let mut h : Heap<i32> = (0..100).rev().collect();
+    h.insert(-1);
+    assert_eq!(Some(-1), h.pop_min());
+    for i in 0..100 {
+        assert_eq!(Some(i), h.pop_min());
+    }
+    assert_eq!(None, h.pop_min());
+}
+
+#[test]
+fn heap_fmt() {
+    let mut h : Heap<i32> = vec![3, 1, 4, 5, 2].into_iter().collect();
+    assert_eq!("Heap{min: 1, len: 5}", format!("{:?}", h));
+    assert_eq!("Heap[1, 2, 3, 4, 5]", format!("{}", h));
+    assert_eq!("Heap{len: 0}", format!("{:?}", Heap::<i32>::new()));
+
+    h = Heap::new();
+    h.insert(1);
+    h.insert(2);
+    assert_eq!("Heap {
+    #0 (rc 1): 1, rank 1, l #1, r #2
+    #1 (rc 1): 2, rank 1, l #2, r #2
+    #2 (rc 4): empty
+}", format!("{:#?}", h));
+
+    // inserting in decreasing order makes a left spine as long as the heap
+    let mut long = Heap::new();
+    for i in (0..10000).rev() {
+        long.insert(i);
+    }
+    assert_eq!("Heap{min: 0, len: 10000}", format!("{:?}", long));
+    assert_eq!(10003, format!("{:#?}", long).lines().count());
+}
+
+#[test]
+fn heap_merge3() {
+    use merge::merge3;
+    let base : Heap<i32> = (0..100).collect();
+    let mut ours = base.clone();
+    ours.pop_min();
+    ours.insert(200);
+    let mut theirs = base.clone();
+    theirs.pop_min();
+    theirs.pop_min();
+    theirs.insert(300);
+    theirs.insert(300);
+    let merged = merge3(&base, &ours, &theirs).unwrap();
+    let expected : Vec<i32> = (2..100).chain(vec![200, 300, 300]).collect();
+    assert_eq!(expected, merged.iter().collect::<Vec<_>>());
+
+    // theirs removes an element ours kept
+    let mut theirs = base.clone();
+    theirs.pop_min();
+    theirs.pop_min();
+    let merged = merge3(&base, &theirs, &ours).unwrap();
+    let expected : Vec<i32> = (2..100).chain(vec![200]).collect();
+    assert_eq!(expected, merged.iter().collect::<Vec<_>>());
+    let merged = merge3(&base, &ours, &theirs).unwrap();
+    assert_eq!(expected, merged.iter().collect::<Vec<_>>());
+
+    assert_eq!(ours.iter().collect::<Vec<_>>(), merge3(&base, &ours, &base).unwrap().iter().collect::<Vec<_>>());
+
+    // a heap holding a subtree twice
+    let mut twice = base.clone();
+    twice.merge(base.clone());
+    let mut ours = twice.clone();
+    ours.pop_min();
+    ours.insert(-1);
+    let mut theirs = twice.clone();
+    theirs.pop_min();
+    theirs.pop_min();
+    theirs.pop_min();
+    let merged = merge3(&twice, &ours, &theirs).unwrap();
+    let mut expected : Vec<i32> = (1..100).chain(2..100).chain(vec![-1]).collect();
+    expected.sort();
+    assert_eq!(expected, merged.iter().collect::<Vec<_>>());
+
+    // a few changes to a large heap only walk the cells they made
+    let big : Heap<i32> = (0..100000).collect();
+    let mut ours = big.clone();
+    ours.pop_min();
+    ours.insert(-1);
+    ours.insert(50000);
+    let delta = ours.delta(&big);
+    assert!(delta.len() < 200, "walked {} elements", delta.len());
+    let changed : Vec<_> = delta.into_iter().filter(|&(_, n)| n != 0).collect();
+    assert_eq!(vec![(&-1, 1), (&0, -1), (&50000, 1)], changed);
+    let mut theirs = big.clone();
+    theirs.pop_min();
+    theirs.pop_min();
+    theirs.insert(7);
+    let merged = merge3(&big, &ours, &theirs).unwrap();
+    let mut expected : Vec<i32> = (2..100000).chain(vec![-1, 7, 50000]).collect();
+    expected.sort();
+    assert_eq!(expected, merged.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn heap_archive() {
+    use archive::{Archive, ArchiveReader};
+    let h1 : Heap<i32> = (0..100).rev().collect();
+    let mut h2 = h1.clone();
+    h2.insert(-1);
+    let mut a = Archive::new();
+    a.push(&h1);
+    a.push(&h2);
+    let bytes = a.into_bytes();
+    let mut r = ArchiveReader::new(&bytes).unwrap();
+    let mut l1 : Heap<i32> = r.read().unwrap();
+    let l2 : Heap<i32> = r.read().unwrap();
+    assert_eq!(h2.iter().collect::<Vec<_>>(), l2.iter().collect::<Vec<_>>());
+    l1.insert(50);
+    assert_eq!(Some(0), l1.pop_min());
+    assert_eq!(100, l1.iter().count());
+
+    // inserting in decreasing order makes a left spine as long as the heap
+    let mut spine = Heap::new();
+    for i in (0..100000).rev() {
+        spine.insert(i);
+    }
+    let mut a = Archive::new();
+    a.push(&spine);
+    let bytes = a.into_bytes();
+    let loaded : Heap<i32> = ArchiveReader::new(&bytes).unwrap().read().unwrap();
+    assert!(loaded.iter().eq(0..100000));
+
+    // a root of 5 with the given rank, over a left child `x` of rank 1
+    let bad = |rank: i32, x: i32| {
+        let mut a = Archive::new();
+        a.write_varint(1);
+        a.write_varint(1);
+        rank.archive(&mut a);
+        5.archive(&mut a);
+        a.write_varint(1);
+        a.write_varint(1);
+        1.archive(&mut a);
+        x.archive(&mut a);
+        // a new empty cell, then three links to it
+        for &tag in &[1, 0, 4, 4, 4] {
+            a.write_varint(tag);
+        }
+        a.into_bytes()
+    };
+    let bytes = bad(1, 2);
+    assert_eq!(Err(ArchiveError::Invalid("heap: a child is smaller than its parent")),
+               ArchiveReader::new(&bytes).unwrap().read::<Heap<i32>>().map(|_| ()));
+    let bytes = bad(1, 7);
+    assert!(ArchiveReader::new(&bytes).unwrap().read::<Heap<i32>>().is_ok());
+    let bytes = bad(2, 7);
+    assert_eq!(Err(ArchiveError::Invalid("heap: wrong rank")),
+               ArchiveReader::new(&bytes).unwrap().read::<Heap<i32>>().map(|_| ()));
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn heap_threads() {
+    use std::thread;
+    let h : Heap<i32> = (0..1000).map(|i| i * 2).collect();
+    let workers : Vec<_> = (0..4).map(|t| {
+        let mut mine = h.clone();
+        thread::spawn(move || {
+            mine.insert(-t);
+            mine.pop_min()
+        })
+    }).collect();
+    for (t, worker) in workers.into_iter().enumerate() {
+        assert_eq!(Some(-(t as i32)), worker.join().unwrap());
+    }
+    assert_eq!((0..1000).map(|i| i * 2).collect::<Vec<_>>(), h.iter().collect::<Vec<_>>());
+}
+
 #[cfg(test)]
 mod bench {
     use test;
     use test::Bencher;
     use super::Heap;
+    use binomial_heap::Heap as SkewHeap;
     use std::collections::VecDeque;
 
     #[bench]
@@ -197,4 +809,86 @@ mod bench {
         );
     }
 
+    #[bench]
+    fn hp_insert_100000(b: &mut Bencher) {
+        let size = 100000;
+        b.iter(
+            || {
+                let mut hp = Heap::new();
+                for i in 0..size {
+                    hp.insert(-i);
+                }
+                test::black_box(hp)
+            }
+        );
+    }
+
+    #[bench]
+    fn hp_from_vec_100000(b: &mut Bencher) {
+        let size = 100000;
+        let vec1 : Vec<i32> = (0..size).map(|i| -i).collect();
+        b.iter(
+            || {
+                // `from_vec` takes the vector, copying it is a memcpy
+                test::black_box(Heap::from_vec(vec1.clone()))
+            }
+        );
+    }
+
+    #[bench]
+    fn bh_insert(b: &mut Bencher) {
+        let mut vec1  = VecDeque::new();
+        let size = 100000;
+        for i in 0..size {
+            vec1.push_back(-i);
+        }
+
+        let mut hp = SkewHeap::new();
+
+        b.iter(
+            || {
+                hp.insert(vec1.pop_front().unwrap());
+                hp.insert(vec1.pop_back().unwrap());
+            }
+        );
+    }
+
+    #[bench]
+    fn bh_insert_100000(b: &mut Bencher) {
+        let size = 100000;
+        b.iter(
+            || {
+                let mut hp = SkewHeap::new();
+                for i in 0..size {
+                    hp.insert(-i);
+                }
+                test::black_box(hp)
+            }
+        );
+    }
+
+    #[bench]
+    fn hp_pop_min(b: &mut Bencher) {
+        let hp : Heap<i32> = (0..100000).collect();
+        b.iter(
+            || {
+                // a copy, so that every run pops from the full heap
+                let mut cln = hp.clone();
+                test::black_box(cln.pop_min());
+            }
+        );
+    }
+
+    #[bench]
+    fn bh_pop_min(b: &mut Bencher) {
+        let hp : SkewHeap<i32> = (0..100000).collect();
+        b.iter(
+            || {
+                // a copy, so that every run pops from the full heap
+                let mut cln = hp.clone();
+                test::black_box(cln.pop_min());
+            }
+        );
+    }
+
 }
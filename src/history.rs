@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use stats::{MemoryStats, total_memory};
+
+/// Names a version in a `History`. Ids are never reused, so an id whose
+/// version was dropped stays unknown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VersionId(usize);
+
+struct Version<C> {
+    value    : C,
+    parent   : Option<usize>,
+    children : Vec<usize>,
+    // the child that `redo` goes to: the one committed or returned from last
+    redo     : Option<usize>,
+}
+
+/// Versions of a collection, kept as a tree: committing after an undo starts
+/// a new branch, and the old one can still be checked out. Every version is a
+/// clone, so versions share the nodes they have in common and keeping many of
+/// them costs little more than the changes between them.
+///
+/// With a limit, committing past it drops the oldest version other than the
+/// current one. Its children take its place under its parent, so that undo
+/// skips over it.
+pub struct History<C: Clone> {
+    versions : BTreeMap<usize, Version<C>>,
+    current  : usize,
+    next     : usize,
+    limit    : Option<usize>,
+}
+
+impl <C: Clone> History<C> {
+    pub fn new(initial: C) -> History<C> {
+        let mut versions = BTreeMap::new();
+        versions.insert(0, Version { value: initial, parent: None, children: vec![], redo: None });
+        History { versions: versions, current: 0, next: 1, limit: None }
+    }
+
+    /// A history that keeps at most `limit` versions. Panics if `limit` is
+    /// zero.
+    pub fn with_limit(initial: C, limit: usize) -> History<C> {
+        let mut ret = History::new(initial);
+        ret.set_limit(Some(limit));
+        ret
+    }
+
+    /// Changes the limit, dropping versions right away if there are more.
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        assert!(limit != Some(0), "history: the limit must be at least one");
+        self.limit = limit;
+        self.trim();
+    }
+
+    /// The number of versions kept.
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn current(&self) -> &C {
+        &self.versions[&self.current].value
+    }
+
+    pub fn current_id(&self) -> VersionId {
+        VersionId(self.current)
+    }
+
+    /// Records `value` as a child of the current version and makes it
+    /// current.
+    pub fn commit(&mut self, value: C) -> VersionId {
+        let id = self.next;
+        self.next += 1;
+        self.versions.insert(id, Version { value: value, parent: Some(self.current), children: vec![], redo: None });
+        {
+            let parent = self.versions.get_mut(&self.current).unwrap();
+            parent.children.push(id);
+            parent.redo = Some(id);
+        }
+        self.current = id;
+        self.trim();
+        VersionId(id)
+    }
+
+    /// Commits a copy of the current version changed by `f`.
+    pub fn edit<F: FnOnce(&mut C)>(&mut self, f: F) -> VersionId {
+        let mut value = self.current().clone();
+        f(&mut value);
+        self.commit(value)
+    }
+
+    /// Goes back to the parent of the current version, if it is kept.
+    pub fn undo(&mut self) -> Option<&C> {
+        let parent = self.versions[&self.current].parent?;
+        self.versions.get_mut(&parent).unwrap().redo = Some(self.current);
+        self.current = parent;
+        Some(self.current())
+    }
+
+    /// Goes forward to the child the last `undo` came from, or the last one
+    /// committed.
+    pub fn redo(&mut self) -> Option<&C> {
+        self.current = self.versions[&self.current].redo?;
+        Some(self.current())
+    }
+
+    pub fn get(&self, id: VersionId) -> Option<&C> {
+        self.versions.get(&id.0).map(|v| &v.value)
+    }
+
+    /// Makes `id` the current version. `redo` from its ancestors then leads
+    /// back to it.
+    pub fn checkout(&mut self, id: VersionId) -> Option<&C> {
+        if !self.versions.contains_key(&id.0) {
+            return None
+        }
+        let mut child = id.0;
+        while let Some(parent) = self.versions[&child].parent {
+            self.versions.get_mut(&parent).unwrap().redo = Some(child);
+            child = parent;
+        }
+        self.current = id.0;
+        Some(self.current())
+    }
+
+    pub fn parent(&self, id: VersionId) -> Option<VersionId> {
+        self.versions.get(&id.0).and_then(|v| v.parent).map(VersionId)
+    }
+
+    /// The branches from `id`, oldest first.
+    pub fn children(&self, id: VersionId) -> Vec<VersionId> {
+        match self.versions.get(&id.0) {
+            Some(v) => v.children.iter().map(|&c| VersionId(c)).collect(),
+            None    => vec![]
+        }
+    }
+
+    /// The ids of the versions kept, oldest first.
+    pub fn ids(&self) -> Vec<VersionId> {
+        self.versions.keys().map(|&id| VersionId(id)).collect()
+    }
+
+    fn trim(&mut self) {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None        => return
+        };
+        while self.versions.len() > limit {
+            let oldest = *self.versions.keys().find(|&&id| id != self.current).unwrap();
+            self.remove(oldest);
+        }
+    }
+
+    // Drops a version, moving its children under its parent.
+    fn remove(&mut self, id: usize) {
+        let v = self.versions.remove(&id).unwrap();
+        for &c in &v.children {
+            self.versions.get_mut(&c).unwrap().parent = v.parent;
+        }
+        if let Some(parent) = v.parent {
+            let parent = self.versions.get_mut(&parent).unwrap();
+            let at = parent.children.iter().position(|&c| c == id).unwrap();
+            let rest = parent.children.split_off(at + 1);
+            parent.children.pop();
+            parent.children.extend(v.children.iter().cloned());
+            parent.children.extend(rest);
+            if parent.redo == Some(id) {
+                parent.redo = v.redo;
+            }
+        }
+    }
+}
+
+impl <C: Clone+MemoryStats> History<C> {
+    /// The bytes taken by all the versions kept, counting the nodes they
+    /// share once.
+    pub fn memory(&self) -> usize {
+        total_memory(self.versions.values().map(|v| &v.value))
+    }
+}
+
+
+#[test]
+fn history_undo_redo() {
+    use list::List;
+    let mut h = History::new(List::new());
+    for i in 0..3 {
+        h.edit(|l| l.prepend(i));
+    }
+    assert_eq!(4, h.len());
+    assert_eq!(Some(&2), h.current().front());
+    assert_eq!(Some(&1), h.undo().unwrap().front());
+    assert_eq!(Some(&0), h.undo().unwrap().front());
+    assert_eq!(Some(&1), h.redo().unwrap().front());
+    h.undo();
+    h.undo();
+    assert!(h.undo().is_none());
+    assert_eq!(0, h.current().len());
+    assert_eq!(Some(&0), h.redo().unwrap().front());
+    assert_eq!(Some(&1), h.redo().unwrap().front());
+    assert_eq!(Some(&2), h.redo().unwrap().front());
+    assert!(h.redo().is_none());
+}
+
+#[test]
+fn history_branches() {
+    use stack::Stack;
+    let mut h = History::new(Stack::new());
+    let a = h.edit(|s| s.push("a"));
+    let b = h.edit(|s| s.push("b"));
+    h.undo();
+    let c = h.edit(|s| s.push("c"));
+    assert_eq!(vec![b, c], h.children(a));
+    assert_eq!(Some(a), h.parent(c));
+    assert_eq!(Some(&"c"), h.current().peek());
+
+    // undo and redo stay on the branch last worked on
+    h.undo();
+    assert_eq!(Some(&"c"), h.redo().unwrap().peek());
+    assert_eq!(Some(&"b"), h.checkout(b).unwrap().peek());
+    h.undo();
+    assert_eq!(Some(&"b"), h.redo().unwrap().peek());
+    assert_eq!(Some(&"c"), h.get(c).unwrap().peek());
+    assert_eq!(2, h.get(c).unwrap().len());
+}
+
+#[test]
+fn history_limit() {
+    use list::List;
+    let mut h = History::with_limit(List::new(), 3);
+    let mut ids = vec![h.current_id()];
+    for i in 0..5 {
+        ids.push(h.edit(|l| l.prepend(i)));
+    }
+    assert_eq!(3, h.len());
+    assert_eq!(ids[3..].to_vec(), h.ids());
+    assert!(h.get(ids[2]).is_none());
+    assert!(h.checkout(ids[0]).is_none());
+    h.undo();
+    h.undo();
+    assert!(h.undo().is_none());
+    assert_eq!(Some(&2), h.current().front());
+
+    // the current version is kept even when it is the oldest
+    h.set_limit(Some(1));
+    assert_eq!(vec![ids[3]], h.ids());
+    assert!(h.redo().is_none());
+}
+
+#[test]
+fn history_memory() {
+    use list::List;
+    use stats::MemoryStats;
+    let mut h = History::new((0..1000).fold(List::new(), |mut l, i| { l.prepend(i); l }));
+    for i in 0..10 {
+        h.edit(|l| l.prepend(i));
+    }
+    // every version is a suffix of the current one, so keeping them all
+    // takes no more than keeping it
+    assert_eq!(h.current().stats().total_bytes(), h.memory());
+    assert!(h.get(h.ids()[0]).unwrap().stats().shared_bytes > 0);
+}
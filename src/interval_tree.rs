@@ -0,0 +1,268 @@
+use std::iter::{Iterator, FromIterator};
+use std::mem::replace;
+use std::ops::Range;
+
+use fingertree;
+use fingertree::{FingerTree, Measure};
+use stats::{MemoryStats, NodeVisitor};
+use show::Disp;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+struct Entry<K: Clone, V> {
+    range : Range<K>,
+    value : V,
+}
+
+// Entries are kept in order of their starts, so a run of them starts at its
+// first start and ends at the greatest end among them (Hinze and Paterson,
+// section 4.8).
+#[derive(Clone, Debug)]
+struct Span<K> {
+    first_start : Option<K>,
+    last_start  : Option<K>,
+    max_end     : Option<K>,
+}
+
+impl <K: Ord+Clone, V> Measure<Entry<K, V>> for Span<K> {
+    fn zero() -> Span<K> {
+        Span { first_start: None, last_start: None, max_end: None }
+    }
+
+    fn of(entry: &Entry<K, V>) -> Span<K> {
+        Span {
+            first_start: Some(entry.range.start.clone()),
+            last_start: Some(entry.range.start.clone()),
+            max_end: Some(entry.range.end.clone()),
+        }
+    }
+
+    fn combine(&self, other: &Span<K>) -> Span<K> {
+        Span {
+            first_start: self.first_start.clone().or_else(|| other.first_start.clone()),
+            last_start: other.last_start.clone().or_else(|| self.last_start.clone()),
+            max_end: match (&self.max_end, &other.max_end) {
+                (&Some(ref x), &Some(ref y)) => Some(if x >= y { x.clone() } else { y.clone() }),
+                (&None, _)                   => other.max_end.clone(),
+                (_, &None)                   => self.max_end.clone()
+            },
+        }
+    }
+}
+
+impl <K: Ord> Span<K> {
+    // Whether some entry of the run may overlap `start..end`.
+    fn overlaps(&self, start: &K, end: &K) -> bool {
+        match (&self.first_start, &self.max_end) {
+            (&Some(ref s), &Some(ref e)) => s < end && e > start,
+            _                            => false
+        }
+    }
+
+    fn contains(&self, point: &K) -> bool {
+        match (&self.first_start, &self.max_end) {
+            (&Some(ref s), &Some(ref e)) => s <= point && e > point,
+            _                            => false
+        }
+    }
+
+    fn starts_after(&self, key: &K) -> bool {
+        self.last_start.as_ref().map_or(false, |s| s > key)
+    }
+
+    fn starts_at_or_after(&self, key: &K) -> bool {
+        self.last_start.as_ref().map_or(false, |s| s >= key)
+    }
+}
+
+/// A persistent map from half-open ranges to values, which finds the ranges
+/// that overlap a point or another range. It is a finger tree ordered by the
+/// starts of the ranges, in which every node also knows the greatest end
+/// below it. Updates take O(log n), queries only descend into the subtrees
+/// that may overlap, and clones are O(1). Several entries may have the same
+/// range.
+#[derive(Clone)]
+pub struct IntervalTree<K: Ord+Clone, V: Clone> {
+    entries : FingerTree<Entry<K, V>, Span<K>>,
+    len     : usize,
+}
+
+impl <K: Ord+Clone, V: Clone> IntervalTree<K, V> {
+    pub fn new() -> IntervalTree<K, V> {
+        IntervalTree { entries: FingerTree::new(), len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts `range` after the entries that start at or before it.
+    pub fn insert(&mut self, range: Range<K>, value: V) {
+        let entries = replace(&mut self.entries, FingerTree::new());
+        let (mut l, r) = entries.split(|span| span.starts_after(&range.start));
+        l.push_back(Entry { range: range, value: value });
+        l.append(r);
+        self.entries = l;
+        self.len += 1;
+    }
+
+    /// Removes the first entry with exactly this range and returns its value.
+    pub fn remove(&mut self, range: &Range<K>) -> Option<V> {
+        let entries = replace(&mut self.entries, FingerTree::new());
+        let (mut l, mut r) = entries.split(|span| span.starts_at_or_after(&range.start));
+        let mut skipped = vec![];
+        let mut ret = None;
+        while let Some(entry) = r.pop_front() {
+            if entry.range.start != range.start {
+                r.push_front(entry);
+                break
+            }
+            if entry.range.end == range.end {
+                ret = Some(entry.value);
+                break
+            }
+            skipped.push(entry);
+        }
+        for entry in skipped.into_iter().rev() {
+            r.push_front(entry);
+        }
+        l.append(r);
+        self.entries = l;
+        if ret.is_some() {
+            self.len -= 1;
+        }
+        ret
+    }
+
+    /// The entries whose range contains `point`, in order of their starts.
+    pub fn query_point(&self, point: &K) -> Vec<(&Range<K>, &V)> {
+        self.entries.filter(|span| span.contains(point))
+            .into_iter().map(|entry| (&entry.range, &entry.value)).collect()
+    }
+
+    /// The entries whose range overlaps `range`, in order of their starts.
+    pub fn query_range(&self, range: &Range<K>) -> Vec<(&Range<K>, &V)> {
+        self.entries.filter(|span| span.overlaps(&range.start, &range.end))
+            .into_iter().map(|entry| (&entry.range, &entry.value)).collect()
+    }
+
+    /// Iterates over the entries in order of their starts.
+    pub fn iter(&self) -> Iter<K, V> {
+        Iter { iter: self.entries.iter() }
+    }
+}
+
+impl <K: Ord+Clone, V: Clone> FromIterator<(Range<K>, V)> for IntervalTree<K, V> {
+    fn from_iter<I: IntoIterator<Item=(Range<K>, V)>>(iter: I) -> IntervalTree<K, V> {
+        let mut ret = IntervalTree::new();
+        for (range, value) in iter {
+            ret.insert(range, value);
+        }
+        ret
+    }
+}
+
+/// `{0..3: "a", 2..5: "b"}`, or with `{:#?}` the tree of entries.
+impl <K: Ord+Clone+fmt::Debug, V: Clone+fmt::Debug> fmt::Debug for IntervalTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("IntervalTree")
+                .field("len", &self.len)
+                .field("entries", &self.entries)
+                .finish()
+        }
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl <K: Ord+Clone+fmt::Display, V: Clone+fmt::Display> fmt::Display for IntervalTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(r, v)| (Disp(format!("{}..{}", r.start, r.end)), Disp(v))))
+            .finish()
+    }
+}
+
+impl <K: Ord+Clone, V: Clone> MemoryStats for IntervalTree<K, V> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.entries.visit_nodes(v)
+    }
+}
+
+pub struct Iter<K: Ord+Clone, V: Clone> {
+    iter : fingertree::Iter<Entry<K, V>, Span<K>>,
+}
+
+impl <K: Ord+Clone, V: Clone> Iterator for Iter<K, V> {
+    type Item = (Range<K>, V);
+    fn next(&mut self) -> Option<(Range<K>, V)> {
+        self.iter.next().map(|entry| (entry.range, entry.value))
+    }
+}
+
+
+#[test]
+fn itree_insert_iter() {
+    let t : IntervalTree<i32, &str> = vec![(5..10, "b"), (1..3, "a"), (5..7, "c"), (8..20, "d")]
+        .into_iter().collect();
+    assert_eq!(4, t.len());
+    // ordered by start, and by insertion for equal starts
+    assert_eq!(vec![(1..3, "a"), (5..10, "b"), (5..7, "c"), (8..20, "d")], t.iter().collect::<Vec<_>>());
+    assert_eq!("{1..3: \"a\", 5..10: \"b\", 5..7: \"c\", 8..20: \"d\"}", format!("{:?}", t));
+    assert_eq!("{1..3: a, 5..10: b, 5..7: c, 8..20: d}", format!("{}", t));
+}
+
+#[test]
+fn itree_queries() {
+    let t : IntervalTree<i32, &str> = vec![(1..3, "a"), (5..10, "b"), (5..7, "c"), (8..20, "d"), (30..31, "e")]
+        .into_iter().collect();
+
+    let values = |xs: Vec<(&Range<i32>, &&'static str)>| xs.into_iter().map(|(_, v)| *v).collect::<Vec<_>>();
+    assert_eq!(vec!["b", "c"], values(t.query_point(&6)));
+    assert_eq!(vec!["b", "d"], values(t.query_point(&9)));
+    assert_eq!(vec!["d"], values(t.query_point(&10)));
+    assert!(t.query_point(&3).is_empty());
+    assert!(t.query_point(&25).is_empty());
+
+    assert_eq!(vec!["a", "b", "c"], values(t.query_range(&(2..6))));
+    assert_eq!(vec!["d", "e"], values(t.query_range(&(15..35))));
+    assert!(t.query_range(&(20..30)).is_empty());
+}
+
+#[test]
+fn itree_remove() {
+    let mut t : IntervalTree<i32, i32> = vec![(1..5, 1), (1..3, 2), (1..5, 3), (2..4, 4)].into_iter().collect();
+    let snapshot = t.clone();
+    assert_eq!(Some(1), t.remove(&(1..5)));
+    assert_eq!(Some(3), t.remove(&(1..5)));
+    assert_eq!(None, t.remove(&(1..5)));
+    assert_eq!(None, t.remove(&(7..8)));
+    assert_eq!(2, t.len());
+    assert_eq!(vec![(1..3, 2), (2..4, 4)], t.iter().collect::<Vec<_>>());
+    assert_eq!(4, snapshot.len());
+    assert_eq!(3, snapshot.query_point(&3).len());
+}
+
+#[test]
+fn itree_against_scan() {
+    let mut t = IntervalTree::new();
+    let mut expected = vec![];
+    let mut seed : u32 = 11;
+    for i in 0..500 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let start = ((seed >> 8) % 1000) as i32;
+        let len = ((seed >> 20) % 50) as i32 + 1;
+        t.insert(start..start + len, i);
+        expected.push((start..start + len, i));
+    }
+    expected.sort_by_key(|e| e.0.start);
+    for p in (0..1100).filter(|p| p % 7 == 0) {
+        let found : Vec<_> = t.query_point(&p).into_iter().map(|(_, v)| *v).collect();
+        let scan : Vec<_> = expected.iter().filter(|e| e.0.start <= p && p < e.0.end).map(|e| e.1).collect();
+        assert_eq!(scan, found);
+    }
+}
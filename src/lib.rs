@@ -1,8 +1,38 @@
 #![feature(test)]
 extern crate test;
 extern crate giftr;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
+
+mod refs;
+mod show;
 
 pub mod deque;
+pub mod realtime_deque;
 pub mod list;
+pub mod stack;
+pub mod queue;
+pub mod hashset;
+pub mod bag;
+pub mod trie;
 pub mod spinelist;
 pub mod heap;
+pub mod binomial_heap;
+pub mod fingertree;
+pub mod rope;
+pub mod interval_tree;
+pub mod union_find;
+pub mod archive;
+pub mod stats;
+pub mod dot;
+pub mod history;
+pub mod diff;
+pub mod merge;
+pub mod cursor;
+
+#[cfg(feature = "serde")]
+mod serde_impls;
@@ -1,13 +1,20 @@
-use giftr::refs::*;
-pub use giftr::refs::functional::Ref as Ref;
+use refs::*;
+pub use refs::Ref;
+use archive::{Archivable, Archive, ArchiveReader, ArchiveError, Link};
+use stats::{MemoryStats, NodeVisitor};
+use dot::{ToDot, Graph};
+use show::{Disp, Dump};
+use diff::{Diff, Edit, PatchError, script, patch_seq};
+use cursor::{self, StackSeq};
 //pub use giftr::refs::imperative::Ref as Ref;
 use std::default::Default;
+use std::fmt;
+use std::fmt::Debug;
 
 use std::mem::replace;
 use std::iter::Iterator;
-use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Node<T: Clone> {
     next: Option<Ref<Node<T>>>,
     elt: Option<T>,
@@ -35,8 +42,6 @@ impl <T: Clone> Node<T> {
         let mut ret = None;
         let mut took_here = false;
         if let Some(ref mut next) = self.next {
-            println!("have next");
-
             if next.is_last() {
                 ret = next.elt.take();
                 took_here = true;
@@ -64,7 +69,7 @@ impl <T: Clone> Drop for Node<T> {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct List<T: Clone> {
     len : i32,
     first : Option<Ref<Node<T>>>,
@@ -92,7 +97,6 @@ impl <T: Clone> List<T> {
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        self.len -= 1;
         let mut ret = None;
         let optfirst = self.first.take();
         if let Some(first) = optfirst {
@@ -100,20 +104,24 @@ impl <T: Clone> List<T> {
             self.first = n.next.take();
             ret = n.elt.take();
         }
+        if ret.is_some() {
+            self.len -= 1;
+        }
         ret
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
+        if self.first.is_none() {
+            return None
+        }
         self.len -= 1;
         let mut ret = None;
         let mut first_is_last = false;
         if let Some(ref mut first) = self.first {
             if first.is_last() {
-                println!("first is last");
                 first_is_last = true;
                 ret = first.elt.take();
             } else {
-                println!("first is not last");
                 ret = first.pop_back();
             }
         }
@@ -127,14 +135,155 @@ impl <T: Clone> List<T> {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    /// O(n), the list has to be walked to its end.
+    pub fn back(&self) -> Option<&T> {
+        if self.len > 0 {
+            self.get(self.len as usize - 1)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        let mut cur = self.first.as_ref();
+        for _ in 0..idx {
+            cur = match cur {
+                Some(node) => node.next.as_ref(),
+                None       => return None
+            }
+        }
+        cur.and_then(|node| node.elt.as_ref())
+    }
+
     pub fn iter(&self) -> Iter<T> {
         Iter { cur: self.first.clone() }
     }
 
+    /// Iterates over references to the elements, where `iter` copies the
+    /// nodes it walks.
+    pub fn elts(&self) -> Elts<'_, T> {
+        Elts { cur: self.first.as_ref().map(|first| &**first) }
+    }
+
     pub fn to_iter(mut self) -> Iter<T> {
         Iter { cur: self.first.take() }
     }
 
+    /// A cursor on the first element. Walking it does not affect `self`.
+    pub fn cursor(&self) -> Cursor<T> {
+        Cursor::new(self.clone())
+    }
+
+    /// A cursor on the first element whose edits are written back to `self`
+    /// when it is dropped.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut::new(self)
+    }
+
+}
+
+/// A zipper over a `List`, see `cursor::Cursor`.
+pub type Cursor<T> = cursor::Cursor<List<T>>;
+
+/// A `Cursor` that can edit the list it was made from.
+pub type CursorMut<'a, T> = cursor::CursorMut<'a, List<T>>;
+
+impl <T: Clone> StackSeq for List<T> {
+    type Elt = T;
+
+    fn empty() -> List<T> {
+        List::new()
+    }
+
+    fn push_front(&mut self, x: T) {
+        self.prepend(x)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        List::pop_front(self)
+    }
+}
+
+/// A `List` being built in place, from `List::transient`. Appends are kept
+/// aside and linked in one pass by `persistent`, which walks the list once
+/// instead of once per element. The nodes it owns alone are changed in
+/// place; those still shared with other lists are copied first, so the
+/// lists the transient was made from do not change.
+pub struct TransientList<T: Clone> {
+    list : List<T>,
+    // appended elements, not linked in yet
+    tail : Vec<T>,
+}
+
+impl <T: Clone> TransientList<T> {
+    pub fn len(&self) -> i32 {
+        self.list.len + self.tail.len() as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.tail.is_empty()
+    }
+
+    pub fn prepend(&mut self, x: T) {
+        self.list.prepend(x)
+    }
+
+    pub fn append(&mut self, x: T) {
+        self.tail.push(x)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.list.is_empty() {
+            self.link();
+        }
+        self.list.pop_front()
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        match self.tail.pop() {
+            Some(x) => Some(x),
+            None    => self.list.pop_back()
+        }
+    }
+
+    /// Freezes the transient into a list.
+    pub fn persistent(mut self) -> List<T> {
+        self.link();
+        self.list
+    }
+
+    // Puts the appended elements at the end of the list.
+    fn link(&mut self) {
+        if self.tail.is_empty() {
+            return
+        }
+        self.list.len += self.tail.len() as i32;
+        let mut chain = None;
+        for x in self.tail.drain(..).rev() {
+            chain = Some(Ref::new(Node { elt: Some(x), next: chain }));
+        }
+        let mut cur = &mut self.list.first;
+        while let Some(node) = cur {
+            cur = &mut node.next;
+        }
+        *cur = chain;
+    }
+}
+
+impl <T: Clone> List<T> {
+    /// A transient holding the elements of `self`, to build on it without
+    /// paying for copy on write at every step.
+    pub fn transient(self) -> TransientList<T> {
+        TransientList { list: self, tail: vec![] }
+    }
 }
 
 pub struct Iter<T: Clone> {
@@ -153,8 +302,198 @@ impl <T: Clone> Iterator for Iter<T> {
     }
 }
 
+pub struct Elts<'a, T: Clone+'a> {
+    cur : Option<&'a Node<T>>,
+}
+
+impl <'a, T: Clone> Iterator for Elts<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(node) = self.cur {
+            self.cur = node.next.as_ref().map(|next| &**next);
+            if let Some(ref x) = node.elt {
+                return Some(x)
+            }
+        }
+        None
+    }
+}
+
+/// `[1, 2, 3]`, or with `{:#?}` the nodes with their strong counts.
+impl <T: Clone+Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return f.debug_list().entries(self.elts()).finish()
+        }
+        let mut d = Dump::new(f, "List")?;
+        d.field("len", &self.len)?;
+        let mut cur = self.first.as_ref();
+        while let Some(node) = cur {
+            if !d.node(node)? {
+                break
+            }
+            let elt : &dyn Debug = match node.elt {
+                Some(ref x) => x,
+                None        => &Disp("-")
+            };
+            match node.next {
+                Some(ref next) => {
+                    let id = d.id(next);
+                    d.line(format_args!("{:?} -> #{}", elt, id))?
+                }
+                None => d.line(format_args!("{:?}", elt))?
+            }
+            cur = node.next.as_ref();
+        }
+        d.finish()
+    }
+}
+
+impl <T: Clone+fmt::Display> fmt::Display for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.elts().map(Disp)).finish()
+    }
+}
+
+impl <T: Clone> List<T> {
+    /// Like `visit_nodes`, also calling `visit_elt` on the element of each
+    /// node visited for the first time, for elements that hold nodes
+    /// themselves.
+    pub fn visit_nodes_with<F: FnMut(&T, &mut NodeVisitor)>(&self, v: &mut NodeVisitor, mut visit_elt: F) {
+        let mut cur = self.first.as_ref();
+        while let Some(node) = cur {
+            if !v.node_ref(node, 0) {
+                return
+            }
+            if let Some(ref x) = node.elt {
+                visit_elt(x, v);
+            }
+            cur = node.next.as_ref();
+        }
+    }
+}
+
+impl <T: Clone> MemoryStats for List<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.visit_nodes_with(v, |_, _| ())
+    }
+}
+
+impl <T: Clone> List<T> {
+    // The number of nodes at the end of `self` that `other` shares. Both
+    // lists are walked from where they have as many nodes left, until they
+    // reach the same one.
+    fn shared_suffix_len(&self, other: &List<T>) -> usize {
+        let (mut a, mut b) = (self.first.as_ref(), other.first.as_ref());
+        let (mut n, mut m) = (self.len as usize, other.len as usize);
+        while n > m {
+            a = a.and_then(|node| node.next.as_ref());
+            n -= 1;
+        }
+        while m > n {
+            b = b.and_then(|node| node.next.as_ref());
+            m -= 1;
+        }
+        while let (Some(x), Some(y)) = (a, b) {
+            if Rc::ptr_eq(&x._ptr, &y._ptr) {
+                return n
+            }
+            a = x.next.as_ref();
+            b = y.next.as_ref();
+            n -= 1;
+        }
+        0
+    }
+}
+
+// Versions derived from one another share their tails, which are skipped
+// whole; only the elements before them are compared.
+impl <T: Clone+PartialEq> Diff for List<T> {
+    type Elt = T;
+
+    fn diff(&self, other: &List<T>) -> Vec<Edit<T>> {
+        let shared = self.shared_suffix_len(other);
+        let old : Vec<&T> = self.elts().take(self.len as usize - shared).collect();
+        let new : Vec<&T> = other.elts().take(other.len as usize - shared).collect();
+        script(&old, &new, 0)
+    }
+
+    // The nodes after the last edit stay shared with `self`.
+    fn patch(&mut self, edits: &[Edit<T>]) -> Result<(), PatchError> {
+        patch_seq(self, edits)
+    }
+}
+
+fn dot_fields<T: Clone+Debug>(node: &Node<T>) -> Vec<String> {
+    match node.elt {
+        Some(ref x) => vec![format!("{:?}", x)],
+        None        => vec!["-".to_string()]
+    }
+}
+
+impl <T: Clone+Debug> ToDot for List<T> {
+    fn to_dot(&self, g: &mut Graph) -> String {
+        let mut cur = match self.first {
+            Some(ref first) => first,
+            None            => return g.record(&["nil".to_string()])
+        };
+        let (root, mut new) = g.node_ref(cur, &dot_fields(cur));
+        let mut prev = root.clone();
+        while new {
+            cur = match cur.next {
+                Some(ref next) => next,
+                None           => break
+            };
+            let (id, is_new) = g.node_ref(cur, &dot_fields(cur));
+            g.edge(&prev, &id, "");
+            prev = id;
+            new = is_new;
+        }
+        root
+    }
+}
+
+// Writes the nodes front to back until one the archive already holds, so
+// that lists sharing a tail write it once. Iterative, as lists can be long.
+impl <T: Clone+Archivable+'static> Archivable for List<T> {
+    fn archive(&self, a: &mut Archive) {
+        self.len.archive(a);
+        let mut cur = self.first.as_ref();
+        while let Some(node) = cur {
+            if !a.begin_node(node) {
+                return
+            }
+            node.elt.archive(a);
+            cur = node.next.as_ref();
+        }
+        a.write_none()
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<List<T>, ArchiveError> {
+        let len = i32::unarchive(r)?;
+        let mut fresh = vec![];
+        let mut first = loop {
+            match r.read_link()? {
+                Link::None         => break None,
+                Link::Shared(node) => break Some(node),
+                Link::New(id)      => fresh.push((id, Option::<T>::unarchive(r)?))
+            }
+        };
+        for (id, elt) in fresh.into_iter().rev() {
+            let node = Ref::new(Node { next: first, elt: elt });
+            r.fill_node(id, &node);
+            first = Some(node);
+        }
+        let ret = List { len: len, first: first };
+        if len < 0 || ret.elts().count() != len as usize {
+            return Err(ArchiveError::Invalid("list: wrong length"))
+        }
+        Ok(ret)
+    }
+}
+
 mod test {
-    use giftr::refs::*;
+    use refs::*;
     use super::{Ref, List};
     #[test]
     fn lst_len() {
@@ -205,6 +544,101 @@ mod test {
         assert_eq!(None, lst.pop_front());
     }
 
+    #[test]
+    fn lst_pop_empty() {
+        let mut lst = List::new();
+        assert_eq!(None, lst.pop_front());
+        assert_eq!(None, lst.pop_back());
+        assert_eq!(0, lst.len());
+        assert!(lst.is_empty());
+
+        lst.prepend(1);
+        assert_eq!(1, lst.len());
+        assert!(!lst.is_empty());
+    }
+
+    #[test]
+    fn lst_get() {
+        let mut lst = List::new();
+        assert_eq!(None, lst.front());
+        assert_eq!(None, lst.back());
+        lst.prepend(3);
+        lst.prepend(2);
+        lst.prepend(1);
+
+        assert_eq!(Some(&1), lst.front());
+        assert_eq!(Some(&3), lst.back());
+        assert_eq!(Some(&1), lst.get(0));
+        assert_eq!(Some(&2), lst.get(1));
+        assert_eq!(Some(&3), lst.get(2));
+        assert_eq!(None,     lst.get(3));
+        assert_eq!(3, lst.len());
+    }
+
+    #[test]
+    fn lst_cursor() {
+        let mut lst = List::new();
+        lst.prepend(3);
+        lst.prepend(2);
+        lst.prepend(1);
+
+        let mut c = lst.cursor();
+        assert_eq!(Some(&1), c.current());
+        assert!(!c.move_prev());
+        assert!(c.move_next());
+        assert!(c.move_next());
+        assert_eq!(Some(&3), c.current());
+        assert!(c.move_next());
+        assert_eq!(None, c.current());
+        assert_eq!(3, c.index());
+        assert!(!c.move_next());
+        assert!(c.move_prev());
+        assert!(c.move_prev());
+        assert_eq!(Some(&2), c.current());
+        assert_eq!(1, c.index());
+
+        let (prefix, suffix) = c.split();
+        assert_eq!(vec![1], prefix.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2, 3], suffix.iter().collect::<Vec<_>>());
+        assert_eq!(vec![1, 2, 3], lst.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lst_cursor_mut() {
+        let mut lst = List::new();
+        lst.prepend(4);
+        lst.prepend(2);
+        lst.prepend(1);
+        let snapshot = lst.clone();
+        {
+            let mut c = lst.cursor_mut();
+            c.move_next();
+            c.insert_after(3);
+            c.insert_before(0);
+            assert_eq!(Some(&2), c.current());
+            assert_eq!(2, c.index());
+            c.move_next();
+            assert_eq!(Some(3), c.remove_current());
+            assert_eq!(Some(&4), c.current());
+            c.move_next();
+            c.insert_after(5);
+            assert_eq!(Some(&5), c.current());
+        }
+        assert_eq!(vec![1, 0, 2, 4, 5], lst.iter().collect::<Vec<_>>());
+        assert_eq!(5, lst.len());
+        assert_eq!(vec![1, 2, 4], snapshot.iter().collect::<Vec<_>>());
+
+        let tail = {
+            let mut c = lst.cursor_mut();
+            c.move_next();
+            c.move_next();
+            c.split()
+        };
+        assert_eq!(vec![1, 0], lst.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2, 4, 5], tail.iter().collect::<Vec<_>>());
+        assert_eq!(2, lst.len());
+    }
+
     #[test]
     fn lst_copy() {
         println!("=== LST_COPY ==============");
@@ -236,6 +670,183 @@ mod test {
         }
     }
 
+    #[cfg(feature = "sync")]
+    #[test]
+    fn lst_threads() {
+        use std::thread;
+        let mut lst = List::new();
+        for i in 0..1000 {
+            lst.prepend(i);
+        }
+        let workers : Vec<_> = (0..4).map(|t| {
+            let mut mine = lst.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    mine.prepend(t * 1000 + i);
+                }
+                mine.pop_back();
+                (mine.len(), mine.pop_front())
+            })
+        }).collect();
+        for (t, worker) in workers.into_iter().enumerate() {
+            assert_eq!((1099, Some(t as i32 * 1000 + 99)), worker.join().unwrap());
+        }
+        assert_eq!(1000, lst.len());
+        assert_eq!(Some(0), lst.pop_back());
+    }
+
+    #[test]
+    fn lst_archive_sharing() {
+        use archive::{Archive, ArchiveReader, ArchiveError};
+        let mut base = List::new();
+        for i in 0..1000 {
+            base.prepend(i);
+        }
+        let versions : Vec<List<i32>> = (0..100).map(|i| {
+            let mut v = base.clone();
+            v.prepend(-i);
+            v
+        }).collect();
+        let mut a = Archive::new();
+        for v in &versions {
+            a.push(v);
+        }
+        let bytes = a.into_bytes();
+        // the shared 1000 elements are written once, not a hundred times
+        assert!(bytes.len() < 5000);
+
+        let mut r = ArchiveReader::new(&bytes).unwrap();
+        let loaded : Vec<List<i32>> = (0..100).map(|_| r.read().unwrap()).collect();
+        assert!(r.is_empty());
+        let tail = |l: &List<i32>| l.first.as_ref().unwrap().next.clone().unwrap();
+        for (i, l) in loaded.iter().enumerate() {
+            assert_eq!(1001, l.len());
+            assert_eq!(versions[i].iter().collect::<Vec<_>>(), l.iter().collect::<Vec<_>>());
+            assert!(Rc::ptr_eq(&tail(&loaded[0])._ptr, &tail(l)._ptr));
+        }
+
+        // the length is checked against the nodes read
+        let mut a = Archive::new();
+        a.push(&versions[0]);
+        let mut bytes = a.into_bytes();
+        bytes[5] ^= 2;
+        let mut r = ArchiveReader::new(&bytes).unwrap();
+        assert_eq!(Err(ArchiveError::Invalid("list: wrong length")), r.read::<List<i32>>().map(|_| ()));
+    }
+
+    #[test]
+    fn lst_fmt() {
+        let mut lst = List::new();
+        for i in (1..4).rev() {
+            lst.prepend(i);
+        }
+        assert_eq!("[1, 2, 3]", format!("{:?}", lst));
+        assert_eq!("[1, 2, 3]", format!("{}", lst));
+        let mut words = List::new();
+        words.prepend("b");
+        words.prepend("a");
+        assert_eq!("[\"a\", \"b\"]", format!("{:?}", words));
+        assert_eq!("[a, b]", format!("{}", words));
+
+        let mut shared = lst.clone();
+        shared.prepend(0);
+        assert_eq!("List {
+    len: 4
+    #0 (rc 1): 0 -> #1
+    #1 (rc 2): 1 -> #2
+    #2 (rc 1): 2 -> #3
+    #3 (rc 1): 3
+}", format!("{:#?}", shared));
+
+        // long lists are written without recursing
+        let mut long = List::new();
+        for i in 0..100000 {
+            long.prepend(i);
+        }
+        assert!(format!("{:?}", long).starts_with("[99999, 99998, "));
+        assert_eq!(100003, format!("{:#?}", long).lines().count());
+    }
+
+    #[test]
+    fn lst_diff() {
+        use diff::{Diff, Edit, PatchError};
+        let mut base = List::new();
+        for i in 0..1000 {
+            base.prepend(i);
+        }
+        let mut ours = base.clone();
+        ours.pop_front();
+        ours.pop_front();
+        ours.prepend(-1);
+        let edits = base.diff(&ours);
+        assert_eq!(vec![Edit::Delete(0), Edit::Replace(0, -1)], edits);
+
+        let mut theirs = base.clone();
+        theirs.append(1000);
+        theirs.patch(&edits).unwrap();
+        let mut expected = ours.iter().collect::<Vec<_>>();
+        expected.push(1000);
+        assert_eq!(expected, theirs.iter().collect::<Vec<_>>());
+        assert_eq!(Vec::<Edit<i32>>::new(), theirs.diff(&theirs.clone()));
+
+        let mut back = ours.clone();
+        back.patch(&ours.diff(&base)).unwrap();
+        assert_eq!(base.iter().collect::<Vec<_>>(), back.iter().collect::<Vec<_>>());
+        assert_eq!(Err(PatchError { edit: 0 }), back.patch(&[Edit::Delete(1000)]));
+        assert_eq!(1000, back.len());
+    }
+
+    #[test]
+    fn lst_diff_shared() {
+        use diff::{Diff, NoEq};
+        // the shared tail must be skipped without comparing its elements
+        let mut base = List::new();
+        for _ in 0..1000 {
+            base.prepend(NoEq);
+        }
+        let mut ours = base.clone();
+        ours.prepend(NoEq);
+        ours.prepend(NoEq);
+        assert_eq!(2, base.diff(&ours).len());
+        assert_eq!(2, ours.diff(&base).len());
+    }
+
+    #[test]
+    fn lst_transient() {
+        let mut t = List::new().transient();
+        for i in 0..1000 {
+            t.append(i);
+        }
+        t.prepend(-1);
+        assert_eq!(1001, t.len());
+        assert_eq!(Some(999), t.pop_back());
+        assert_eq!(Some(-1), t.pop_front());
+        assert_eq!(Some(0), t.pop_front());
+        let lst = t.persistent();
+        assert_eq!(998, lst.len());
+        assert_eq!((1..999).collect::<Vec<_>>(), lst.iter().collect::<Vec<_>>());
+
+        // lists sharing nodes with the transient do not see its edits
+        let base = lst.clone();
+        let mut t = lst.transient();
+        t.append(999);
+        t.prepend(0);
+        let lst = t.persistent();
+        assert_eq!(998, base.len());
+        assert_eq!((1..999).collect::<Vec<_>>(), base.iter().collect::<Vec<_>>());
+        assert_eq!((0..1000).collect::<Vec<_>>(), lst.iter().collect::<Vec<_>>());
+
+        // nodes owned by the transient alone are not copied
+        drop(base);
+        let addr = |l: &List<i32>| &*l.first.as_ref().unwrap()._ptr as *const super::Node<i32>;
+        let first = addr(&lst);
+        let mut t = lst.transient();
+        t.append(1000);
+        let lst = t.persistent();
+        assert_eq!(first, addr(&lst));
+        assert_eq!(Some(&1000), lst.back());
+    }
+
 }
 
 
@@ -243,7 +854,7 @@ mod test {
 mod bench {
     use test;
     use test::Bencher;
-    use giftr::refs::*;
+    use giftr::refs::imperative;
     use super::{Ref, List};
 
     #[bench]
@@ -260,6 +871,45 @@ mod bench {
         );
     }
 
+    #[bench]
+    fn lst_transient_append(b: &mut Bencher) {
+        b.iter(
+            || {
+                let mut t = List::new().transient();
+                for i in 0..10000 {
+                    t.append(i);
+                }
+                test::black_box(t.persistent())
+            }
+        );
+    }
+
+    // The nodes of a `List` over giftr's imperative `Ref`, the mode the
+    // transient keeps up with: boxed, changed in place and never copied.
+    struct ImperativeNode {
+        elt  : i32,
+        next : Option<imperative::Ref<ImperativeNode>>,
+    }
+
+    // The same 10000 elements as `lst_transient_append`, loaded back to
+    // front, which is as quick as the imperative mode gets.
+    #[bench]
+    fn lst_imperative_build(b: &mut Bencher) {
+        b.iter(
+            || {
+                let mut first = None;
+                for i in (0..10000).rev() {
+                    first = Some(imperative::Ref::new(ImperativeNode { elt: i, next: first }));
+                }
+                test::black_box(first.as_ref().map(|node| node.elt));
+                // taken apart one node at a time, as `List` drops its nodes
+                while let Some(mut node) = first {
+                    first = node.next.take();
+                }
+            }
+        );
+    }
+
     #[bench]
     fn lst_prepend(b: &mut Bencher) {
         let mut lst1 : List<i32> = List::new();
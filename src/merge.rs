@@ -0,0 +1,242 @@
+use std::cmp::{min, max};
+
+use diff::{Diff, Edit};
+use list::List;
+use spinelist::SpineList;
+use deque::Deque;
+use std::fmt::Debug;
+
+/// A region of the base that the two sides changed in different ways.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict<T> {
+    /// The position of the region in the base.
+    pub at     : usize,
+    pub base   : Vec<T>,
+    pub ours   : Vec<T>,
+    pub theirs : Vec<T>,
+}
+
+/// Collections that can reconcile two versions made from a common base.
+pub trait Merge3: Sized {
+    type Elt;
+
+    /// The base with the changes of both `ours` and `theirs`, or the regions
+    /// where they clash.
+    fn merge3(base: &Self, ours: &Self, theirs: &Self) -> Result<Self, Vec<Conflict<Self::Elt>>>;
+}
+
+pub fn merge3<C: Merge3>(base: &C, ours: &C, theirs: &C) -> Result<C, Vec<Conflict<C::Elt>>> {
+    C::merge3(base, ours, theirs)
+}
+
+// The elements of `start..end` in the base become `elts`.
+#[derive(PartialEq)]
+struct Hunk<T> {
+    start : usize,
+    end   : usize,
+    elts  : Vec<T>,
+}
+
+// Turns a script of `Diff` into hunks in the positions of the base, joining
+// the edits that touch.
+fn hunks<T>(edits: Vec<Edit<T>>) -> Vec<Hunk<T>> {
+    let mut ret : Vec<Hunk<T>> = vec![];
+    // the position in the patched sequence minus that in the base
+    let mut delta = 0isize;
+    for edit in edits {
+        let (at, removed, x) = match edit {
+            Edit::Insert(at, x)  => (at, 0, Some(x)),
+            Edit::Delete(at)     => (at, 1, None),
+            Edit::Replace(at, x) => (at, 1, Some(x))
+        };
+        let start = (at as isize - delta) as usize;
+        delta += x.is_some() as isize - removed as isize;
+        if ret.last().map_or(false, |last| last.end == start) {
+            let last = ret.last_mut().unwrap();
+            last.end = start + removed;
+            last.elts.extend(x);
+        } else {
+            ret.push(Hunk { start: start, end: start + removed, elts: x.into_iter().collect() });
+        }
+    }
+    ret
+}
+
+// The region `start..end` of the base with `hunks` applied.
+fn region<T: Clone>(base: &[T], start: usize, end: usize, hunks: &[&Hunk<T>]) -> Vec<T> {
+    let mut ret = vec![];
+    let mut pos = start;
+    for h in hunks {
+        ret.extend_from_slice(&base[pos..h.start]);
+        ret.extend_from_slice(&h.elts);
+        pos = h.end;
+    }
+    ret.extend_from_slice(&base[pos..end]);
+    ret
+}
+
+/// The three-way merge of sequences, done on the scripts that turn the base
+/// into either side. Changes to regions of the base that overlap or touch
+/// clash, unless both sides made the same one. The merged value is the base
+/// patched, so it shares what the base and the two sides kept. `elts` lists
+/// the elements of the base, and is only called to describe conflicts.
+pub fn merge_seq<C, T, F>(base: &C, ours: &C, theirs: &C, elts: F) -> Result<C, Vec<Conflict<T>>>
+    where C: Diff<Elt=T>+Clone, T: Clone+PartialEq, F: Fn(&C) -> Vec<T> {
+    let ours_edits = base.diff(ours);
+    if ours_edits.is_empty() {
+        return Ok(theirs.clone())
+    }
+    let theirs_edits = base.diff(theirs);
+    if theirs_edits.is_empty() {
+        return Ok(ours.clone())
+    }
+    let (a, b) = (hunks(ours_edits), hunks(theirs_edits));
+
+    let mut merged : Vec<&Hunk<T>> = vec![];
+    let mut clashes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        let touch = |x: &Hunk<T>, y: &Hunk<T>| x.start <= y.end && y.start <= x.end;
+        if j == b.len() || (i < a.len() && !touch(&a[i], &b[j]) && a[i].start < b[j].start) {
+            merged.push(&a[i]);
+            i += 1;
+            continue
+        }
+        if i == a.len() || !touch(&a[i], &b[j]) {
+            merged.push(&b[j]);
+            j += 1;
+            continue
+        }
+        // gather the hunks of both sides that touch one another
+        let (i0, j0) = (i, j);
+        let start = min(a[i].start, b[j].start);
+        let mut end = max(a[i].end, b[j].end);
+        i += 1;
+        j += 1;
+        loop {
+            if i < a.len() && a[i].start <= end {
+                end = max(end, a[i].end);
+                i += 1;
+            } else if j < b.len() && b[j].start <= end {
+                end = max(end, b[j].end);
+                j += 1;
+            } else {
+                break
+            }
+        }
+        if i - i0 == 1 && j - j0 == 1 && a[i0] == b[j0] {
+            merged.push(&a[i0]);
+        } else {
+            clashes.push((start, end, i0..i, j0..j));
+        }
+    }
+
+    if !clashes.is_empty() {
+        let base_elts = elts(base);
+        return Err(clashes.into_iter().map(|(start, end, ra, rb)| {
+            let ra : Vec<_> = a[ra].iter().collect();
+            let rb : Vec<_> = b[rb].iter().collect();
+            Conflict {
+                at: start,
+                base: base_elts[start..end].to_vec(),
+                ours: region(&base_elts, start, end, &ra),
+                theirs: region(&base_elts, start, end, &rb),
+            }
+        }).collect())
+    }
+
+    let mut edits = vec![];
+    let mut delta = 0isize;
+    for h in merged {
+        let at = (h.start as isize + delta) as usize;
+        let removed = h.end - h.start;
+        let kept = min(removed, h.elts.len());
+        for (k, x) in h.elts.iter().enumerate() {
+            if k < kept {
+                edits.push(Edit::Replace(at + k, x.clone()));
+            } else {
+                edits.push(Edit::Insert(at + k, x.clone()));
+            }
+        }
+        for _ in kept..removed {
+            edits.push(Edit::Delete(at + kept));
+        }
+        delta += h.elts.len() as isize - removed as isize;
+    }
+    let mut ret = base.clone();
+    ret.patch(&edits).expect("merge: hunks out of bounds of the base");
+    Ok(ret)
+}
+
+impl <T: Clone+PartialEq> Merge3 for List<T> {
+    type Elt = T;
+    fn merge3(base: &List<T>, ours: &List<T>, theirs: &List<T>) -> Result<List<T>, Vec<Conflict<T>>> {
+        merge_seq(base, ours, theirs, |l| l.iter().collect())
+    }
+}
+
+impl <T: Clone+Debug+PartialEq> Merge3 for SpineList<T> {
+    type Elt = T;
+    fn merge3(base: &SpineList<T>, ours: &SpineList<T>, theirs: &SpineList<T>) -> Result<SpineList<T>, Vec<Conflict<T>>> {
+        merge_seq(base, ours, theirs, |l| l.iter().collect())
+    }
+}
+
+impl <T: Clone+PartialEq> Merge3 for Deque<T> {
+    type Elt = T;
+    fn merge3(base: &Deque<T>, ours: &Deque<T>, theirs: &Deque<T>) -> Result<Deque<T>, Vec<Conflict<T>>> {
+        merge_seq(base, ours, theirs, |d| d.iter().collect())
+    }
+}
+
+
+#[test]
+fn merge_lists() {
+    let base : List<i32> = (0..10).rev().fold(List::new(), |mut l, i| { l.prepend(i); l });
+    let edit = |f: &dyn Fn(&mut Vec<i32>)| {
+        let mut v : Vec<i32> = base.iter().collect();
+        f(&mut v);
+        v.into_iter().rev().fold(List::new(), |mut l, i| { l.prepend(i); l })
+    };
+    let ours = edit(&|v: &mut Vec<i32>| { v.insert(2, 20); v.remove(8); });
+    let theirs = edit(&|v: &mut Vec<i32>| { v[5] = 50; v.push(10); });
+    let merged = merge3(&base, &ours, &theirs).unwrap();
+    assert_eq!(vec![0, 1, 20, 2, 3, 4, 50, 6, 8, 9, 10], merged.iter().collect::<Vec<_>>());
+
+    // the same change on both sides is taken once
+    let same = merge3(&base, &ours, &ours.clone()).unwrap();
+    assert_eq!(ours.iter().collect::<Vec<_>>(), same.iter().collect::<Vec<_>>());
+
+    let clash = edit(&|v: &mut Vec<i32>| v[2] = 30);
+    let conflicts = merge3(&base, &ours, &clash).unwrap_err();
+    assert_eq!(vec![Conflict { at: 2, base: vec![2], ours: vec![20, 2], theirs: vec![30] }], conflicts);
+}
+
+#[test]
+fn merge_shared() {
+    // a side that is still the base is merged without diffing the other
+    let mut base = List::new();
+    for i in 0..1000 {
+        base.prepend(i);
+    }
+    let mut ours = base.clone();
+    ours.prepend(1000);
+    let mut theirs = base.clone();
+    theirs.prepend(1001);
+    assert_eq!(Some(&1000), merge3(&base, &ours, &base.clone()).unwrap().front());
+    assert_eq!(Some(&1001), merge3(&base, &base.clone(), &theirs).unwrap().front());
+    // both insert at the front
+    assert_eq!(1, merge3(&base, &ours, &theirs).unwrap_err().len());
+
+    let mut d = Deque::new();
+    for i in 0..1000 {
+        d.push_back(i);
+    }
+    let mut ours = d.clone();
+    ours.push_front(-1);
+    let mut theirs = d.clone();
+    theirs.push_back(1000);
+    let merged = merge3(&d, &ours, &theirs).unwrap();
+    assert_eq!(1002, merged.len());
+    assert_eq!((Some(&-1), Some(&1000)), (merged.front(), merged.back()));
+}
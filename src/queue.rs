@@ -0,0 +1,282 @@
+use std::fmt;
+use std::iter::Iterator;
+use std::mem::replace;
+
+use list::List;
+use stats::{MemoryStats, NodeVisitor};
+use show::Disp;
+
+// The state of an incremental rotation, which computes `f ++ reverse(r)` a
+// few steps per operation. `ok` counts the elements of `f_rev` that have not
+// been dequeued in the meantime.
+#[derive(Clone, Debug)]
+enum Rotation<T: Clone> {
+    Idle,
+    Reversing { ok: usize, f: List<T>, f_rev: List<T>, r: List<T>, r_rev: List<T> },
+    Appending { ok: usize, f_rev: List<T>, r_rev: List<T> },
+    Done(List<T>),
+}
+
+impl <T: Clone> Rotation<T> {
+    fn exec(self) -> Rotation<T> {
+        match self {
+            Rotation::Reversing { ok, mut f, mut f_rev, mut r, mut r_rev } => {
+                if let Some(x) = f.pop_front() {
+                    f_rev.prepend(x);
+                    r_rev.prepend(r.pop_front().unwrap());
+                    Rotation::Reversing { ok: ok + 1, f: f, f_rev: f_rev, r: r, r_rev: r_rev }
+                } else {
+                    // the rear was one longer than the front
+                    r_rev.prepend(r.pop_front().unwrap());
+                    Rotation::Appending { ok: ok, f_rev: f_rev, r_rev: r_rev }
+                }
+            }
+            Rotation::Appending { ok: 0, r_rev, .. } => Rotation::Done(r_rev),
+            Rotation::Appending { ok, mut f_rev, mut r_rev } => {
+                r_rev.prepend(f_rev.pop_front().unwrap());
+                Rotation::Appending { ok: ok - 1, f_rev: f_rev, r_rev: r_rev }
+            }
+            state => state
+        }
+    }
+
+    // Accounts for an element that was dequeued from the old front while the
+    // rotation is still copying it.
+    fn invalidate(self) -> Rotation<T> {
+        match self {
+            Rotation::Reversing { ok, f, f_rev, r, r_rev } => {
+                Rotation::Reversing { ok: ok - 1, f: f, f_rev: f_rev, r: r, r_rev: r_rev }
+            }
+            Rotation::Appending { ok: 0, mut r_rev, .. } => {
+                r_rev.pop_front();
+                Rotation::Done(r_rev)
+            }
+            Rotation::Appending { ok, f_rev, r_rev } => {
+                Rotation::Appending { ok: ok - 1, f_rev: f_rev, r_rev: r_rev }
+            }
+            state => state
+        }
+    }
+}
+
+/// A persistent FIFO queue with worst-case O(1) operations (Hood and
+/// Melville; see Okasaki, "Purely Functional Data Structures", section 8.2.1).
+/// When the rear list grows longer than the front, the two are rotated
+/// incrementally, two steps per operation. No step is ever deferred to a later
+/// operation, so the bounds also hold when old versions are reused.
+#[derive(Clone)]
+pub struct Queue<T: Clone> {
+    lenf  : usize,
+    front : List<T>,
+    state : Rotation<T>,
+    lenr  : usize,
+    rear  : List<T>,
+}
+
+impl <T: Clone> Queue<T> {
+    pub fn new() -> Queue<T> {
+        Queue { lenf: 0, front: List::new(), state: Rotation::Idle, lenr: 0, rear: List::new() }
+    }
+
+    fn exec2(&mut self) {
+        let state = replace(&mut self.state, Rotation::Idle);
+        match state.exec().exec() {
+            Rotation::Done(front) => self.front = front,
+            state                 => self.state = state
+        }
+    }
+
+    fn check(&mut self) {
+        if self.lenr > self.lenf {
+            let f = self.front.clone();
+            let r = replace(&mut self.rear, List::new());
+            self.state = Rotation::Reversing { ok: 0, f: f, f_rev: List::new(), r: r, r_rev: List::new() };
+            self.lenf += self.lenr;
+            self.lenr = 0;
+        }
+        self.exec2();
+    }
+
+    pub fn enqueue(&mut self, x: T) {
+        self.rear.prepend(x);
+        self.lenr += 1;
+        self.check();
+    }
+
+    pub fn dequeue(&mut self) -> Option<T> {
+        let ret = self.front.pop_front();
+        if ret.is_some() {
+            self.lenf -= 1;
+            let state = replace(&mut self.state, Rotation::Idle);
+            self.state = state.invalidate();
+            self.check();
+        }
+        ret
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.front.front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lenf + self.lenr
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        self.clone().to_iter()
+    }
+
+    pub fn to_iter(self) -> Iter<T> {
+        Iter { queue: self }
+    }
+}
+
+// A rotation in progress holds lists that share nodes with the front, which
+// are counted once.
+impl <T: Clone> MemoryStats for Queue<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.front.visit_nodes(v);
+        self.rear.visit_nodes(v);
+        match self.state {
+            Rotation::Idle => {}
+            Rotation::Reversing { ref f, ref f_rev, ref r, ref r_rev, .. } => {
+                f.visit_nodes(v);
+                f_rev.visit_nodes(v);
+                r.visit_nodes(v);
+                r_rev.visit_nodes(v);
+            }
+            Rotation::Appending { ref f_rev, ref r_rev, .. } => {
+                f_rev.visit_nodes(v);
+                r_rev.visit_nodes(v);
+            }
+            Rotation::Done(ref l) => l.visit_nodes(v)
+        }
+    }
+}
+
+/// `Queue[1, 2, 3]` from front to rear, or with `{:#?}` the lists and the
+/// rotation in progress.
+impl <T: Clone+fmt::Debug> fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Queue")
+                .field("lenf", &self.lenf)
+                .field("front", &self.front)
+                .field("state", &self.state)
+                .field("lenr", &self.lenr)
+                .field("rear", &self.rear)
+                .finish()
+        }
+        f.write_str("Queue")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl <T: Clone+fmt::Display> fmt::Display for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Queue")?;
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+pub struct Iter<T: Clone> {
+    queue : Queue<T>,
+}
+
+impl <T: Clone> Iterator for Iter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.queue.dequeue()
+    }
+}
+
+
+#[test]
+fn queue_fifo() {
+    let mut q = Queue::new();
+    assert_eq!(None, q.peek());
+    assert_eq!(None, q.dequeue());
+    q.enqueue(1);
+    q.enqueue(2);
+    q.enqueue(3);
+    assert_eq!(3, q.len());
+    assert_eq!(Some(&1), q.peek());
+
+    assert_eq!(Some(1), q.dequeue());
+    q.enqueue(4);
+    assert_eq!(Some(2), q.dequeue());
+    assert_eq!(Some(3), q.dequeue());
+    assert_eq!(Some(4), q.dequeue());
+    assert_eq!(None, q.dequeue());
+    assert!(q.is_empty());
+}
+
+#[test]
+fn queue_iter() {
+    let mut q = Queue::new();
+    for i in 0..100 {
+        q.enqueue(i);
+    }
+    let mut i = 0;
+    for v in q.iter() {
+        assert_eq!(i, v);
+        i += 1;
+    }
+    assert_eq!(100, i);
+    assert_eq!(100, q.len());
+}
+
+#[test]
+fn queue_fmt() {
+    let mut q = Queue::new();
+    assert_eq!("Queue[]", format!("{:?}", q));
+    q.enqueue("a");
+    q.enqueue("b");
+    q.enqueue("c");
+    q.dequeue();
+    assert_eq!("Queue[\"b\", \"c\"]", format!("{:?}", q));
+    assert_eq!("Queue[b, c]", format!("{}", q));
+    assert!(format!("{:#?}", q).starts_with("Queue {\n    lenf: "));
+}
+
+#[test]
+fn queue_many_versions() {
+    let mut q = Queue::new();
+    for i in 0..1000 {
+        q.enqueue(i);
+    }
+    let versions : Vec<Queue<i32>> = (0..10).map(|_| q.clone()).collect();
+    for (n, mut v) in versions.into_iter().enumerate() {
+        v.enqueue(-(n as i32));
+        for i in 0..1000 {
+            assert_eq!(Some(i), v.dequeue());
+        }
+        assert_eq!(Some(-(n as i32)), v.dequeue());
+        assert_eq!(None, v.dequeue());
+    }
+    assert_eq!(1000, q.len());
+    assert_eq!(Some(&0), q.peek());
+}
+
+#[test]
+fn queue_against_vecdeque() {
+    use std::collections::VecDeque;
+    let mut q = Queue::new();
+    let mut expected = VecDeque::new();
+    let mut seed : u32 = 3;
+    for i in 0..5000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        if (seed >> 16) % 3 == 0 {
+            assert_eq!(expected.pop_front(), q.dequeue());
+        } else {
+            q.enqueue(i);
+            expected.push_back(i);
+        }
+        assert_eq!(expected.len(), q.len());
+        assert_eq!(expected.front(), q.peek());
+    }
+}
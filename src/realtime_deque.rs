@@ -0,0 +1,614 @@
+use std::cell::{OnceCell, RefCell};
+use std::fmt;
+use std::fmt::Debug;
+use std::iter::Iterator;
+use std::mem::swap;
+use std::ops::Index;
+use std::rc::Rc;
+
+use stats::{MemoryStats, NodeVisitor};
+use show::{Disp, deque_parts};
+
+// The balance constant: neither side may grow longer than `C` times the other
+// side plus one. Okasaki allows 2 or 3.
+const C : usize = 3;
+
+// Memoized lazy streams. Unlike the rest of the crate these can not sit on
+// giftr's functional `Ref`: a suspension that is forced through one version of
+// the deque must stay forced for every other version sharing it, so the cell
+// is updated in place instead of being copied on write.
+//
+// The suspended computations are the three stream functions below, kept as
+// data rather than boxed closures so that `T` need not be `'static`.
+enum Thunk<T: Clone> {
+    Take(usize, Stream<T>),
+    RotateRev(Stream<T>, Stream<T>, Stream<T>),
+    RotateDrop(Stream<T>, usize, Stream<T>),
+}
+
+impl <T: Clone> Thunk<T> {
+    fn run(self) -> Cell<T> {
+        match self {
+            Thunk::Take(n, s)          => take_cell(n, s),
+            Thunk::RotateRev(r, f, a)  => rotate_rev_cell(r, f, a),
+            Thunk::RotateDrop(r, i, f) => rotate_drop_cell(r, i, f),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Cell<T: Clone> {
+    Nil,
+    Cons(T, Stream<T>),
+}
+
+struct Susp<T: Clone> {
+    value : OnceCell<Cell<T>>,
+    thunk : RefCell<Option<Thunk<T>>>,
+}
+
+impl <T: Clone> Drop for Susp<T> {
+    fn drop(&mut self) {
+        let mut optcur = match self.value.take() {
+            Some(Cell::Cons(_, next)) => Some(next),
+            _                         => None
+        };
+        while let Some(cur) = optcur {
+            optcur = match Rc::try_unwrap(cur.0) {
+                Ok(mut susp) => match susp.value.take() {
+                    Some(Cell::Cons(_, next)) => Some(next),
+                    _                         => None
+                },
+                Err(_) => None
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Stream<T: Clone>(Rc<Susp<T>>);
+
+impl <T: Clone> Stream<T> {
+    fn nil() -> Stream<T> {
+        Stream::from_cell(Cell::Nil)
+    }
+
+    fn cons(x: T, s: Stream<T>) -> Stream<T> {
+        Stream::from_cell(Cell::Cons(x, s))
+    }
+
+    fn from_cell(c: Cell<T>) -> Stream<T> {
+        let value = OnceCell::new();
+        let _ = value.set(c);
+        Stream(Rc::new(Susp { value: value, thunk: RefCell::new(None) }))
+    }
+
+    fn lazy(t: Thunk<T>) -> Stream<T> {
+        Stream(Rc::new(Susp { value: OnceCell::new(), thunk: RefCell::new(Some(t)) }))
+    }
+
+    fn force(&self) -> &Cell<T> {
+        let susp = &self.0;
+        susp.value.get_or_init(|| {
+            let t = susp.thunk.borrow_mut().take().expect("suspension forced twice");
+            t.run()
+        })
+    }
+
+    fn head(&self) -> Option<&T> {
+        match *self.force() {
+            Cell::Nil           => None,
+            Cell::Cons(ref x, _) => Some(x)
+        }
+    }
+
+    fn get(&self, idx: usize) -> Option<&T> {
+        let mut cur = self;
+        for _ in 0..idx {
+            cur = match *cur.force() {
+                Cell::Nil               => return None,
+                Cell::Cons(_, ref next) => next
+            }
+        }
+        cur.head()
+    }
+}
+
+// Forces one more cell of a schedule.
+fn exec1<T: Clone>(s: &Stream<T>) -> Stream<T> {
+    match *s.force() {
+        Cell::Nil               => s.clone(),
+        Cell::Cons(_, ref next) => next.clone()
+    }
+}
+
+fn exec2<T: Clone>(s: &Stream<T>) -> Stream<T> {
+    exec1(&exec1(s))
+}
+
+fn take<T: Clone>(n: usize, s: Stream<T>) -> Stream<T> {
+    Stream::lazy(Thunk::Take(n, s))
+}
+
+fn take_cell<T: Clone>(n: usize, s: Stream<T>) -> Cell<T> {
+    if n == 0 {
+        return Cell::Nil
+    }
+    match *s.force() {
+        Cell::Nil                   => Cell::Nil,
+        Cell::Cons(ref x, ref next) => Cell::Cons(x.clone(), take(n - 1, next.clone()))
+    }
+}
+
+// Only ever called with small `n`, so dropping eagerly is O(1).
+fn drop_n<T: Clone>(n: usize, s: Stream<T>) -> Stream<T> {
+    let mut s = s;
+    for _ in 0..n {
+        let next = match *s.force() {
+            Cell::Nil               => break,
+            Cell::Cons(_, ref next) => next.clone()
+        };
+        s = next;
+    }
+    s
+}
+
+// Prepends the first `n` elements of `f`, reversed, to `a`.
+fn rev_onto<T: Clone>(f: Stream<T>, n: usize, a: Stream<T>) -> Stream<T> {
+    let mut f = f;
+    let mut a = a;
+    for _ in 0..n {
+        let next = match *f.force() {
+            Cell::Nil => break,
+            Cell::Cons(ref x, ref next) => {
+                a = Stream::cons(x.clone(), a);
+                next.clone()
+            }
+        };
+        f = next;
+    }
+    a
+}
+
+// r ++ reverse(f) ++ a, reversing `C` elements of `f` per element of `r`.
+fn rotate_rev<T: Clone>(r: Stream<T>, f: Stream<T>, a: Stream<T>) -> Stream<T> {
+    Stream::lazy(Thunk::RotateRev(r, f, a))
+}
+
+fn rotate_rev_cell<T: Clone>(r: Stream<T>, f: Stream<T>, a: Stream<T>) -> Cell<T> {
+    match *r.force() {
+        Cell::Nil => {
+            let s = rev_onto(f, usize::max_value(), a);
+            let ret = s.force().clone();
+            ret
+        }
+        Cell::Cons(ref x, ref next) => {
+            let rest = rotate_rev(next.clone(), drop_n(C, f.clone()), rev_onto(f, C, a));
+            Cell::Cons(x.clone(), rest)
+        }
+    }
+}
+
+// r ++ reverse(drop(i, f))
+fn rotate_drop<T: Clone>(r: Stream<T>, i: usize, f: Stream<T>) -> Stream<T> {
+    Stream::lazy(Thunk::RotateDrop(r, i, f))
+}
+
+fn rotate_drop_cell<T: Clone>(r: Stream<T>, i: usize, f: Stream<T>) -> Cell<T> {
+    if i < C {
+        let s = rotate_rev(r, drop_n(i, f), Stream::nil());
+        let ret = s.force().clone();
+        return ret
+    }
+    match *r.force() {
+        Cell::Nil => panic!("rotate_drop: rear stream too short"),
+        Cell::Cons(ref x, ref next) => {
+            Cell::Cons(x.clone(), rotate_drop(next.clone(), i - C, drop_n(C, f)))
+        }
+    }
+}
+
+/// A persistent deque with worst-case O(1) operations (Okasaki, "Purely
+/// Functional Data Structures", section 8.4.3). Rotations are performed
+/// incrementally through lazy streams whose results are memoized and shared
+/// between versions, so the bounds hold even when old versions are reused.
+/// The API mirrors `deque::Deque`.
+#[derive(Clone)]
+pub struct Deque<T: Clone> {
+    lenf  : usize,
+    front : Stream<T>,
+    // the part of `front` that is still to be forced
+    sched_front : Stream<T>,
+    lenb  : usize,
+    back  : Stream<T>,
+    sched_back : Stream<T>,
+}
+
+impl <T: Clone> Deque<T> {
+    pub fn new() -> Deque<T> {
+        let nil = Stream::nil();
+        Deque {
+            lenf: 0, front: nil.clone(), sched_front: nil.clone(),
+            lenb: 0, back: nil.clone(), sched_back: nil,
+        }
+    }
+
+    fn check(&mut self) {
+        let (lenf, lenb) = (self.lenf, self.lenb);
+        if lenf > C * lenb + 1 {
+            let i = (lenf + lenb) / 2;
+            let j = lenf + lenb - i;
+            let front = take(i, self.front.clone());
+            let back = rotate_drop(self.back.clone(), i, self.front.clone());
+            *self = Deque {
+                lenf: i, front: front.clone(), sched_front: front,
+                lenb: j, back: back.clone(), sched_back: back,
+            };
+        } else if lenb > C * lenf + 1 {
+            let j = (lenf + lenb) / 2;
+            let i = lenf + lenb - j;
+            let back = take(j, self.back.clone());
+            let front = rotate_drop(self.front.clone(), j, self.back.clone());
+            *self = Deque {
+                lenf: i, front: front.clone(), sched_front: front,
+                lenb: j, back: back.clone(), sched_back: back,
+            };
+        }
+    }
+
+    pub fn push_front(&mut self, x: T) {
+        self.lenf += 1;
+        self.front = Stream::cons(x, self.front.clone());
+        self.sched_front = exec1(&self.sched_front);
+        self.sched_back = exec1(&self.sched_back);
+        self.check();
+    }
+
+    pub fn push_back(&mut self, x: T) {
+        self.lenb += 1;
+        self.back = Stream::cons(x, self.back.clone());
+        self.sched_front = exec1(&self.sched_front);
+        self.sched_back = exec1(&self.sched_back);
+        self.check();
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        let popped = match *self.front.force() {
+            Cell::Nil                   => None,
+            Cell::Cons(ref x, ref next) => Some((x.clone(), next.clone()))
+        };
+        if let Some((x, next)) = popped {
+            self.lenf -= 1;
+            self.front = next;
+            self.sched_front = exec2(&self.sched_front);
+            self.sched_back = exec2(&self.sched_back);
+            self.check();
+            Some(x)
+        } else {
+            // with an empty front, the balance invariant leaves at most one
+            // element in the back
+            let ret = self.back.head().cloned();
+            *self = Deque::new();
+            ret
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.reverse();
+        let ret = self.pop_front();
+        self.reverse();
+        ret
+    }
+
+    pub fn reverse(&mut self) {
+        swap(&mut self.lenf, &mut self.lenb);
+        swap(&mut self.front, &mut self.back);
+        swap(&mut self.sched_front, &mut self.sched_back);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lenf + self.lenb
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.front.head().or_else(|| self.back.head())
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.back.head().or_else(|| self.front.head())
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        if idx < self.lenf {
+            self.front.get(idx)
+        } else if idx - self.lenf < self.lenb {
+            self.back.get(self.lenb - 1 - (idx - self.lenf))
+        } else {
+            None
+        }
+    }
+
+    fn to_iter(self) -> Iter<T> {
+        Iter { deque: self }
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        self.clone().to_iter()
+    }
+
+    pub fn rev_iter(&self) -> Iter<T> {
+        let mut cln = self.clone();
+        cln.reverse();
+        cln.to_iter()
+    }
+}
+
+impl <T: Clone> Index<usize> for Deque<T> {
+    type Output = T;
+    fn index(&self, idx: usize) -> &T {
+        self.get(idx).expect("deque index out of bounds")
+    }
+}
+
+impl <T: Clone> Deque<T> {
+    // Writes `Deque[1, 2 | 3, 4]`, with the front part before the bar and
+    // the back part after it, both in order.
+    fn fmt_with<F: Fn(&T, &mut fmt::Formatter) -> fmt::Result>(&self, f: &mut fmt::Formatter, each: F) -> fmt::Result {
+        let mut front = Vec::with_capacity(self.lenf);
+        let mut cur = &self.front;
+        while let Cell::Cons(ref x, ref next) = *cur.force() {
+            front.push(x);
+            cur = next;
+        }
+        let mut back = Vec::with_capacity(self.lenb);
+        let mut cur = &self.back;
+        while let Cell::Cons(ref x, ref next) = *cur.force() {
+            back.push(x);
+            cur = next;
+        }
+        back.reverse();
+        deque_parts(f, &front, &back, each)
+    }
+}
+
+/// `Deque[1, 2 | 3, 4]`, or with `{:#?}` the streams as far as they have
+/// been evaluated.
+impl <T: Clone+Debug> Debug for Deque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Deque")
+                .field("lenf", &self.lenf)
+                .field("front", &Evaluated(&self.front))
+                .field("sched_front", &Evaluated(&self.sched_front))
+                .field("lenb", &self.lenb)
+                .field("back", &Evaluated(&self.back))
+                .field("sched_back", &Evaluated(&self.sched_back))
+                .finish()
+        }
+        self.fmt_with(f, |x, f| Debug::fmt(x, f))
+    }
+}
+
+impl <T: Clone+fmt::Display> fmt::Display for Deque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with(f, |x, f| fmt::Display::fmt(x, f))
+    }
+}
+
+// The cells of a stream that have been evaluated, followed by `<lazy>` if
+// the stream goes on. Writing it forces nothing.
+struct Evaluated<'a, T: Clone+'a>(&'a Stream<T>);
+
+impl <'a, T: Clone+Debug> Debug for Evaluated<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut cur = self.0;
+        loop {
+            match cur.0.value.get() {
+                Some(&Cell::Cons(ref x, ref next)) => {
+                    list.entry(x);
+                    cur = next;
+                }
+                Some(&Cell::Nil) => break,
+                None => {
+                    list.entry(&Disp("<lazy>"));
+                    break
+                }
+            }
+        }
+        list.finish()
+    }
+}
+
+// Visiting does not force anything: the cells of a stream are followed as far
+// as they have been evaluated, and what an unevaluated suspension captures
+// is not seen.
+fn visit_stream<T: Clone>(s: &Stream<T>, v: &mut NodeVisitor) {
+    let mut cur = s;
+    while v.node(&*cur.0, Rc::strong_count(&cur.0), 0) {
+        cur = match cur.0.value.get() {
+            Some(&Cell::Cons(_, ref next)) => next,
+            _                              => return
+        };
+    }
+}
+
+impl <T: Clone> MemoryStats for Deque<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        visit_stream(&self.front, v);
+        visit_stream(&self.sched_front, v);
+        visit_stream(&self.back, v);
+        visit_stream(&self.sched_back, v)
+    }
+}
+
+pub struct Iter<T: Clone> {
+    deque : Deque<T>,
+}
+
+impl <T: Clone> Iterator for Iter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.deque.pop_front()
+    }
+}
+
+
+#[test]
+fn rtdeque_pop_front() {
+    let mut d = Deque::new();
+    d.push_front(2);
+    d.push_front(1);
+    d.push_back(3);
+
+    assert_eq!(Some(1), d.pop_front());
+    assert_eq!(Some(2), d.pop_front());
+    assert_eq!(Some(3), d.pop_front());
+    assert_eq!(None, d.pop_front());
+}
+
+#[test]
+fn rtdeque_pop_back() {
+    let mut d = Deque::new();
+    d.push_front(2);
+    d.push_front(1);
+    d.push_back(3);
+
+    assert_eq!(Some(3), d.pop_back());
+    assert_eq!(Some(2), d.pop_back());
+    assert_eq!(Some(1), d.pop_back());
+    assert_eq!(None, d.pop_back());
+}
+
+#[test]
+fn rtdeque_reverse() {
+    let mut d = Deque::new();
+    d.push_front(2);
+    d.push_front(1);
+    d.push_back(3);
+    d.push_back(4);
+
+    d.reverse();
+
+    assert_eq!(Some(4), d.pop_front());
+    assert_eq!(Some(3), d.pop_front());
+    assert_eq!(Some(2), d.pop_front());
+    assert_eq!(Some(1), d.pop_front());
+    assert_eq!(None, d.pop_back());
+}
+
+#[test]
+fn rtdeque_iter() {
+    let mut d = Deque::new();
+    for i in 0..100 {
+        d.push_back(i);
+    }
+
+    let mut i = 0;
+    for v in d.iter() {
+        assert_eq!(i, v);
+        i += 1;
+    }
+    assert_eq!(100, i);
+
+    for v in d.rev_iter() {
+        i -= 1;
+        assert_eq!(i, v);
+    }
+    assert_eq!(0, i);
+    assert_eq!(100, d.len());
+}
+
+#[test]
+fn rtdeque_borrowed() {
+    // elements need not be 'static
+    let words : Vec<String> = (0..50).map(|i| i.to_string()).collect();
+    let mut d = Deque::new();
+    for w in &words {
+        d.push_front(w.as_str());
+    }
+    for w in words.iter().rev() {
+        assert_eq!(Some(w.as_str()), d.pop_front());
+    }
+    assert_eq!(None, d.pop_back());
+}
+
+#[test]
+fn rtdeque_get() {
+    let mut d = Deque::new();
+    for i in 0..10 {
+        d.push_back(i);
+    }
+    assert_eq!(Some(&0), d.front());
+    assert_eq!(Some(&9), d.back());
+    for i in 0..10 {
+        assert_eq!(i, d[i]);
+    }
+    assert_eq!(None, d.get(10));
+}
+
+#[test]
+fn rtdeque_many_versions() {
+    let mut d = Deque::new();
+    for i in 0..1000 {
+        d.push_back(i);
+    }
+
+    // every version pops the whole deque; memoized rotations are shared, and
+    // no version may observe another one's pops
+    let versions : Vec<Deque<i32>> = (0..10).map(|_| d.clone()).collect();
+    for mut v in versions {
+        for i in 0..500 {
+            assert_eq!(Some(i), v.pop_front());
+            assert_eq!(Some(999 - i), v.pop_back());
+        }
+        assert!(v.is_empty());
+        assert_eq!(None, v.pop_front());
+    }
+    assert_eq!(1000, d.len());
+    assert_eq!(Some(0), d.pop_front());
+}
+
+#[test]
+fn rtdeque_against_vecdeque() {
+    use std::collections::VecDeque;
+    let mut d = Deque::new();
+    let mut expected = VecDeque::new();
+    let mut seed : u32 = 17;
+    for i in 0..5000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        match (seed >> 16) % 5 {
+            0 => { d.push_front(i); expected.push_front(i) }
+            1 => { d.push_back(i); expected.push_back(i) }
+            2 => assert_eq!(expected.pop_front(), d.pop_front()),
+            3 => assert_eq!(expected.pop_back(), d.pop_back()),
+            _ => { d.reverse(); expected = expected.into_iter().rev().collect() }
+        }
+        assert_eq!(expected.len(), d.len());
+        assert_eq!(expected.front(), d.front());
+        assert_eq!(expected.back(), d.back());
+    }
+}
+
+#[test]
+fn rtdeque_fmt() {
+    let mut d = Deque::new();
+    assert_eq!("Deque[]", format!("{:?}", d));
+    d.push_back(3);
+    d.push_back(4);
+    d.push_front(2);
+    d.push_front(1);
+    // pushing 4 moved half of the back to the front
+    assert_eq!("Deque[1, 2, 3 | 4]", format!("{:?}", d));
+    assert!(format!("{:#?}", d).starts_with("Deque {\n    lenf: 3,"));
+
+    let mut words = Deque::new();
+    words.push_back("a");
+    assert_eq!("Deque[\"a\"]", format!("{:?}", words));
+    words.reverse();
+    assert_eq!("Deque[a]", format!("{}", words));
+}
@@ -0,0 +1,129 @@
+// The shared pointers the collections are built from. By default these are
+// giftr's functional `Ref` and `Rc`. With the `sync` feature they become an
+// `Arc`-based `Ref` with the same interface, so that snapshots of the
+// collections are `Send + Sync` and can be handed to other threads. `Rc` is
+// then an alias for `Arc`, so that `Rc::try_unwrap(r._ptr)` and
+// `Rc::ptr_eq` keep working on either flavor.
+//
+// giftr's `ispine` is `Rc`-based whatever this module says, so with `sync`
+// `SpineList` switches to a spine of its own built on this `Ref`. The
+// real-time deque and the union-find structure update their shared cells in
+// place and use `Rc` directly.
+
+#[cfg(not(feature = "sync"))]
+pub use giftr::refs::*;
+#[cfg(not(feature = "sync"))]
+pub use giftr::refs::functional::Ref;
+#[cfg(not(feature = "sync"))]
+pub use std::rc::Rc;
+
+#[cfg(feature = "sync")]
+pub use self::arc::Ref;
+#[cfg(feature = "sync")]
+pub use std::sync::Arc as Rc;
+
+#[cfg(feature = "sync")]
+mod arc {
+    use std::fmt;
+    use std::mem;
+    use std::ops::{Deref, DerefMut};
+    use std::process;
+    use std::ptr;
+    use std::sync::Arc;
+
+    /// A functional reference over an `Arc`: clones share the value, and
+    /// writing through a shared reference copies it first.
+    pub struct Ref<T> {
+        pub _ptr : Arc<T>,
+    }
+
+    impl <T: Clone> Ref<T> {
+        pub fn new(x: T) -> Ref<T> {
+            Ref { _ptr: Arc::new(x) }
+        }
+
+        /// The value, moved out if this is the only reference to it and
+        /// cloned otherwise.
+        pub fn consume(self) -> T {
+            match Arc::try_unwrap(self._ptr) {
+                Ok(x)    => x,
+                Err(ptr) => (*ptr).clone()
+            }
+        }
+
+        /// Replaces the value by `f` applied to it. The value is moved into
+        /// `f` if this is the only reference to it, and cloned otherwise.
+        pub fn apply<F: FnOnce(T) -> T>(&mut self, f: F) {
+            let slot = Arc::make_mut(&mut self._ptr);
+            // `slot` holds no value while `f` runs, so a panic in `f` must
+            // not unwind past here and drop it again.
+            let guard = AbortOnUnwind;
+            unsafe {
+                let x = ptr::read(slot);
+                ptr::write(slot, f(x));
+            }
+            mem::forget(guard);
+        }
+    }
+
+    struct AbortOnUnwind;
+
+    impl Drop for AbortOnUnwind {
+        fn drop(&mut self) {
+            process::abort()
+        }
+    }
+
+    impl <T> Clone for Ref<T> {
+        fn clone(&self) -> Ref<T> {
+            Ref { _ptr: self._ptr.clone() }
+        }
+    }
+
+    impl <T> Deref for Ref<T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            &self._ptr
+        }
+    }
+
+    impl <T: Clone> DerefMut for Ref<T> {
+        fn deref_mut(&mut self) -> &mut T {
+            Arc::make_mut(&mut self._ptr)
+        }
+    }
+
+    impl <T: fmt::Debug> fmt::Debug for Ref<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            (**self).fmt(f)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sync"))]
+mod test {
+    use std::cell::Cell;
+    use super::Ref;
+
+    thread_local!(static CLONES : Cell<usize> = Cell::new(0));
+
+    struct Counted(i32);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Counted {
+            CLONES.with(|c| c.set(c.get() + 1));
+            Counted(self.0)
+        }
+    }
+
+    #[test]
+    fn ref_apply() {
+        let mut r = Ref::new(Counted(1));
+        r.apply(|x| Counted(x.0 + 1));
+        assert_eq!(0, CLONES.with(|c| c.get()));
+        let shared = r.clone();
+        r.apply(|x| Counted(x.0 * 10));
+        assert_eq!(1, CLONES.with(|c| c.get()));
+        assert_eq!((20, 2), (r.0, shared.0));
+    }
+}
@@ -0,0 +1,377 @@
+use std::cmp::min;
+use std::fmt;
+use std::iter::Iterator;
+use std::mem::replace;
+use std::ops::Range;
+
+use fingertree;
+use fingertree::{FingerTree, Measure};
+use stats::{MemoryStats, NodeVisitor};
+
+// Chunks are split when they would grow beyond this many bytes.
+const MAX_CHUNK : usize = 1024;
+
+/// The size of a piece of text, counted in bytes, chars and newlines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextSize {
+    pub bytes    : usize,
+    pub chars    : usize,
+    pub newlines : usize,
+}
+
+impl TextSize {
+    fn of_str(s: &str) -> TextSize {
+        TextSize {
+            bytes: s.len(),
+            chars: s.chars().count(),
+            newlines: s.bytes().filter(|b| *b == b'\n').count(),
+        }
+    }
+}
+
+// A chunk caches its size, so that measuring it is O(1).
+#[derive(Clone, Debug)]
+struct Chunk {
+    text : String,
+    size : TextSize,
+}
+
+impl Chunk {
+    fn new(text: &str) -> Chunk {
+        Chunk { text: text.to_string(), size: TextSize::of_str(text) }
+    }
+}
+
+impl Measure<Chunk> for TextSize {
+    fn zero() -> TextSize {
+        TextSize::default()
+    }
+
+    fn of(chunk: &Chunk) -> TextSize {
+        chunk.size
+    }
+
+    fn combine(&self, other: &TextSize) -> TextSize {
+        TextSize {
+            bytes: self.bytes + other.bytes,
+            chars: self.chars + other.chars,
+            newlines: self.newlines + other.newlines,
+        }
+    }
+}
+
+/// A persistent text buffer: a balanced tree of string chunks with shared
+/// nodes. Edits take O(log n) and clones are O(1), which makes snapshots for
+/// undo cheap. Positions are byte offsets unless stated otherwise, and must
+/// lie on char boundaries.
+#[derive(Clone)]
+pub struct Rope {
+    chunks : FingerTree<Chunk, TextSize>,
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope { chunks: FingerTree::new() }
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.chunks.measure().bytes
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.chunks.measure().chars
+    }
+
+    /// The number of lines, that is, one more than the number of newlines.
+    pub fn len_lines(&self) -> usize {
+        self.chunks.measure().newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_bytes() == 0
+    }
+
+    // Splits before `byte_idx`, cutting a chunk in two if needed.
+    fn split_at(self, byte_idx: usize) -> (Rope, Rope) {
+        assert!(byte_idx <= self.len_bytes(), "rope: byte index out of bounds");
+        let (mut l, mut r) = self.chunks.split(|size| size.bytes > byte_idx);
+        let offset = byte_idx - l.measure().bytes;
+        if offset > 0 {
+            let chunk = r.pop_front().unwrap();
+            assert!(chunk.text.is_char_boundary(offset), "rope: byte index is not a char boundary");
+            let (a, b) = chunk.text.split_at(offset);
+            l.push_back(Chunk::new(a));
+            r.push_front(Chunk::new(b));
+        }
+        (Rope { chunks: l }, Rope { chunks: r })
+    }
+
+    /// Moves the text of `other` to the end of `self`, merging the chunks at
+    /// the seam if they are small.
+    pub fn append(&mut self, other: Rope) {
+        let mut other = other.chunks;
+        let merge = match (self.chunks.back(), other.front()) {
+            (Some(a), Some(b)) => a.size.bytes + b.size.bytes <= MAX_CHUNK,
+            _                  => false
+        };
+        if merge {
+            let mut a = self.chunks.pop_back().unwrap();
+            let b = other.pop_front().unwrap();
+            a.text.push_str(&b.text);
+            self.chunks.push_back(Chunk::new(&a.text));
+        }
+        self.chunks.append(other);
+    }
+
+    pub fn insert(&mut self, byte_idx: usize, text: &str) {
+        let rope = replace(self, Rope::new());
+        let (mut l, r) = rope.split_at(byte_idx);
+        l.append(Rope::from(text));
+        l.append(r);
+        *self = l;
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end, "rope: invalid range");
+        let rope = replace(self, Rope::new());
+        let (mut l, r) = rope.split_at(range.start);
+        let (_, r) = r.split_at(range.end - range.start);
+        l.append(r);
+        *self = l;
+    }
+
+    /// The text in `range`, sharing all but at most two chunks with `self`.
+    pub fn slice(&self, range: Range<usize>) -> Rope {
+        assert!(range.start <= range.end, "rope: invalid range");
+        let (_, r) = self.clone().split_at(range.start);
+        let (ret, _) = r.split_at(range.end - range.start);
+        ret
+    }
+
+    /// The text of line `line_idx`, including its trailing newline.
+    pub fn line(&self, line_idx: usize) -> Rope {
+        let start = self.line_to_byte(line_idx);
+        let end = if line_idx + 1 < self.len_lines() {
+            self.line_to_byte(line_idx + 1)
+        } else {
+            self.len_bytes()
+        };
+        self.slice(start..end)
+    }
+
+    // The chunk containing `byte_idx`, its text up to `byte_idx`, and the size
+    // of the text before the chunk. Positions at the very end map to the end
+    // of the last chunk.
+    fn chunk_at_byte(&self, byte_idx: usize) -> Option<(&str, TextSize)> {
+        assert!(byte_idx <= self.len_bytes(), "rope: byte index out of bounds");
+        if byte_idx == self.len_bytes() {
+            return self.chunks.back().map(|chunk| {
+                let before = TextSize {
+                    bytes: self.len_bytes() - chunk.size.bytes,
+                    chars: self.len_chars() - chunk.size.chars,
+                    newlines: self.len_lines() - 1 - chunk.size.newlines,
+                };
+                (&chunk.text[..], before)
+            })
+        }
+        self.chunks.find_with_measure(|size| size.bytes > byte_idx)
+            .map(|(chunk, before)| (&chunk.text[..byte_idx - before.bytes], before))
+    }
+
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        match self.chunk_at_byte(byte_idx) {
+            Some((text, before)) => before.chars + text.chars().count(),
+            None                 => 0
+        }
+    }
+
+    /// The line that `byte_idx` lies on.
+    pub fn byte_to_line(&self, byte_idx: usize) -> usize {
+        match self.chunk_at_byte(byte_idx) {
+            Some((text, before)) => before.newlines + text.bytes().filter(|b| *b == b'\n').count(),
+            None                 => 0
+        }
+    }
+
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        assert!(char_idx <= self.len_chars(), "rope: char index out of bounds");
+        if char_idx == self.len_chars() {
+            return self.len_bytes()
+        }
+        let (chunk, before) = self.chunks.find_with_measure(|size| size.chars > char_idx).unwrap();
+        let (offset, _) = chunk.text.char_indices().nth(char_idx - before.chars).unwrap();
+        before.bytes + offset
+    }
+
+    /// The byte index at which line `line_idx` starts.
+    pub fn line_to_byte(&self, line_idx: usize) -> usize {
+        assert!(line_idx < self.len_lines(), "rope: line index out of bounds");
+        if line_idx == 0 {
+            return 0
+        }
+        // the chunk holding the newline that ends line `line_idx - 1`
+        let (chunk, before) = self.chunks.find_with_measure(|size| size.newlines >= line_idx).unwrap();
+        let (offset, _) = chunk.text.match_indices('\n').nth(line_idx - 1 - before.newlines).unwrap();
+        before.bytes + offset + 1
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.byte_to_line(self.char_to_byte(char_idx))
+    }
+
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.byte_to_char(self.line_to_byte(line_idx))
+    }
+
+    /// Iterates over the chunks of text, in order.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks { iter: self.chunks.elts() }
+    }
+}
+
+impl <'a> From<&'a str> for Rope {
+    fn from(text: &'a str) -> Rope {
+        let mut chunks = FingerTree::new();
+        let mut rest = text;
+        while !rest.is_empty() {
+            let mut end = min(MAX_CHUNK, rest.len());
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            chunks.push_back(Chunk::new(&rest[..end]));
+            rest = &rest[end..];
+        }
+        Rope { chunks: chunks }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// `Rope("text")`, or with `{:#?}` the tree of chunks.
+impl fmt::Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Rope").field("chunks", &self.chunks).finish()
+        }
+        write!(f, "Rope({:?})", self.to_string())
+    }
+}
+
+impl MemoryStats for Rope {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.chunks.visit_nodes_with(v, &|chunk: &Chunk| chunk.text.capacity())
+    }
+}
+
+pub struct Chunks<'a> {
+    iter: fingertree::Elts<'a, Chunk, TextSize>,
+}
+
+impl <'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<&'a str> {
+        self.iter.next().map(|chunk| &chunk.text[..])
+    }
+}
+
+
+#[test]
+fn rope_insert_remove() {
+    let mut r = Rope::from("hello world");
+    r.insert(5, ",");
+    r.insert(r.len_bytes(), "!");
+    r.insert(0, ">> ");
+    assert_eq!(">> hello, world!", r.to_string());
+
+    r.remove(0..3);
+    r.remove(5..6);
+    assert_eq!("hello world!", r.to_string());
+    assert_eq!("world", r.slice(6..11).to_string());
+    assert_eq!("Rope(\"hello world!\")", format!("{:?}", r));
+}
+
+#[test]
+fn rope_against_string() {
+    let mut r = Rope::new();
+    let mut expected = String::new();
+    let mut seed : u32 = 7;
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let rnd = (seed >> 8) as usize;
+        // pick a char boundary
+        let mut idx = if expected.is_empty() { 0 } else { rnd % (expected.len() + 1) };
+        while !expected.is_char_boundary(idx) {
+            idx -= 1;
+        }
+        if rnd % 3 == 0 && idx < expected.len() {
+            let mut end = min(expected.len(), idx + rnd % 300);
+            while !expected.is_char_boundary(end) {
+                end -= 1;
+            }
+            r.remove(idx..end);
+            expected = format!("{}{}", &expected[..idx], &expected[end..]);
+        } else {
+            let text = match rnd % 4 {
+                0 => "äöü\n",
+                1 => "lorem ipsum dolor sit amet ",
+                2 => "\n",
+                _ => "x",
+            }.repeat(rnd % 50);
+            r.insert(idx, &text);
+            expected.insert_str(idx, &text);
+        }
+        assert_eq!(expected.len(), r.len_bytes());
+    }
+    assert_eq!(expected, r.to_string());
+    assert_eq!(expected.chars().count(), r.len_chars());
+    assert_eq!(expected.matches('\n').count() + 1, r.len_lines());
+    for chunk in r.chunks() {
+        assert!(chunk.len() <= MAX_CHUNK);
+    }
+}
+
+#[test]
+fn rope_conversions() {
+    let r = Rope::from("ab\nçd\n\nef");
+    assert_eq!(4, r.len_lines());
+    assert_eq!(9, r.len_chars());
+    assert_eq!(10, r.len_bytes());
+
+    assert_eq!(0, r.line_to_byte(0));
+    assert_eq!(3, r.line_to_byte(1));
+    assert_eq!(7, r.line_to_byte(2));
+    assert_eq!(8, r.line_to_byte(3));
+    assert_eq!(7, r.line_to_char(3));
+
+    assert_eq!(0, r.byte_to_line(2));
+    assert_eq!(1, r.byte_to_line(3));
+    assert_eq!(3, r.byte_to_line(10));
+    assert_eq!(4, r.byte_to_char(5));
+    assert_eq!(5, r.char_to_byte(4));
+    assert_eq!(10, r.char_to_byte(9));
+    assert_eq!(2, r.char_to_line(6));
+
+    assert_eq!("çd\n", r.line(1).to_string());
+    assert_eq!("ef", r.line(3).to_string());
+}
+
+#[test]
+fn rope_snapshots() {
+    let text : String = (0..10000).map(|i| format!("line {}\n", i)).collect();
+    let mut r = Rope::from(&text[..]);
+    let snapshot = r.clone();
+    r.remove(0..r.line_to_byte(5000));
+    r.insert(0, "edited\n");
+
+    assert_eq!(text, snapshot.to_string());
+    assert_eq!(10001, snapshot.len_lines());
+    assert_eq!(5002, r.len_lines());
+    assert_eq!("line 5000\n", r.line(1).to_string());
+}
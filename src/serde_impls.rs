@@ -0,0 +1,510 @@
+// `Serialize` and `Deserialize` for the collections, behind the `serde`
+// feature. Everything is written as a plain sequence of its elements, in
+// iteration order, so that the format does not depend on the internal shape
+// of a version; deserializing rebuilds the collection through its public
+// interface. `HeapCell` is the exception: it is written as the tree it is,
+// flattened into a list of nodes, and its invariants are checked on the way
+// back in.
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error;
+use serde::ser::SerializeSeq;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::ops::Range;
+
+use refs::*;
+use bag::Bag;
+use binomial_heap::Heap as SkewHeap;
+use deque::Deque;
+use fingertree::{FingerTree, Measure};
+use hashset::HashSet;
+use heap::{Heap, HeapCell};
+use interval_tree::IntervalTree;
+use list::List;
+use queue::Queue;
+use realtime_deque::Deque as RealTimeDeque;
+use rope::Rope;
+use spinelist::SpineList;
+use stack::Stack;
+use trie::Trie;
+use union_find::UnionFind;
+
+fn serialize_seq<S, I>(serializer: S, len: usize, iter: I) -> Result<S::Ok, S::Error>
+    where S: Serializer, I: IntoIterator, I::Item: Serialize
+{
+    let mut seq = serializer.serialize_seq(Some(len))?;
+    for x in iter {
+        seq.serialize_element(&x)?;
+    }
+    seq.end()
+}
+
+impl <T: Clone+Serialize> Serialize for List<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len() as usize, self.iter())
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>> Deserialize<'de> for List<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<List<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        // the length counter of a list is an i32
+        if xs.len() > i32::max_value() as usize {
+            return Err(D::Error::custom("list: too many elements"))
+        }
+        let mut ret = List::new();
+        for x in xs.into_iter().rev() {
+            ret.prepend(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Clone+Debug+Serialize> Serialize for SpineList<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Clone+Debug+Deserialize<'de>> Deserialize<'de> for SpineList<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SpineList<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        let mut ret = SpineList::new();
+        for x in xs.into_iter().rev() {
+            ret.prepend(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Clone+Serialize> Serialize for Deque<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>> Deserialize<'de> for Deque<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Deque<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        let mut ret = Deque::new();
+        for x in xs {
+            ret.push_back(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Clone+Serialize> Serialize for RealTimeDeque<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>> Deserialize<'de> for RealTimeDeque<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RealTimeDeque<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        let mut ret = RealTimeDeque::new();
+        for x in xs {
+            ret.push_back(x);
+        }
+        Ok(ret)
+    }
+}
+
+/// Written from the top of the stack down.
+impl <T: Clone+Serialize> Serialize for Stack<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>> Deserialize<'de> for Stack<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Stack<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        let mut ret = Stack::new();
+        for x in xs.into_iter().rev() {
+            ret.push(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Clone+Serialize> Serialize for Queue<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>> Deserialize<'de> for Queue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Queue<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        let mut ret = Queue::new();
+        for x in xs {
+            ret.enqueue(x);
+        }
+        Ok(ret)
+    }
+}
+
+impl <T: Clone+Serialize, M: Measure<T>> Serialize for FingerTree<T, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let xs : Vec<T> = self.iter().collect();
+        xs.serialize(serializer)
+    }
+}
+
+impl <'de, T: Clone+Deserialize<'de>, M: Measure<T>> Deserialize<'de> for FingerTree<T, M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FingerTree<T, M>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        Ok(xs.into_iter().collect())
+    }
+}
+
+/// Written as its text.
+impl Serialize for Rope {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl <'de> Deserialize<'de> for Rope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rope, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Ok(Rope::from(&text[..]))
+    }
+}
+
+/// Written in ascending order.
+impl <T: Ord+Clone+Serialize> Serialize for Heap<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let xs : Vec<T> = self.iter().collect();
+        xs.serialize(serializer)
+    }
+}
+
+impl <'de, T: Ord+Clone+Deserialize<'de>> Deserialize<'de> for Heap<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Heap<T>, D::Error> {
+        Ok(Heap::from_vec(Vec::deserialize(deserializer)?))
+    }
+}
+
+fn rank<T: Ord+Clone>(h: &HeapCell<T>) -> i32 {
+    match *h {
+        HeapCell::Empty              => 0,
+        HeapCell::Node { rank, .. }  => rank
+    }
+}
+
+fn root<T: Ord+Clone>(h: &HeapCell<T>) -> Option<&T> {
+    match *h {
+        HeapCell::Empty                 => None,
+        HeapCell::Node { ref elt, .. }  => Some(elt)
+    }
+}
+
+/// Written as its nodes in pre-order, each as `(rank, elt, left, right)`
+/// where `left` and `right` are the indices of the children, or `None` for
+/// an empty one. The empty heap is `[]`. A flat sequence does not nest, so
+/// long left spines do not run into recursion limits.
+impl <T: Ord+Clone+Serialize> Serialize for HeapCell<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut nodes : Vec<(i32, &T, Option<usize>, Option<usize>)> = vec![];
+        // cells to write, with the node whose left or right child they are
+        let mut stack : Vec<(&HeapCell<T>, Option<(usize, bool)>)> = vec![(self, None)];
+        while let Some((cell, parent)) = stack.pop() {
+            if let HeapCell::Node { rank, ref elt, ref left, ref right } = *cell {
+                let idx = nodes.len();
+                match parent {
+                    Some((p, true))  => nodes[p].2 = Some(idx),
+                    Some((p, false)) => nodes[p].3 = Some(idx),
+                    None             => {}
+                }
+                nodes.push((rank, elt, None, None));
+                stack.push((right, Some((idx, false))));
+                stack.push((left, Some((idx, true))));
+            }
+        }
+        serialize_seq(serializer, nodes.len(), nodes)
+    }
+}
+
+/// Fails unless the children of every node come after it and every node but
+/// the first is the child of exactly one other, so that the nodes form a
+/// tree; and unless every node is at most its children, its rank is one more
+/// than that of its right child, and its left child has at least the rank
+/// of its right child. The empty children all share one cell.
+impl <'de, T: Ord+Clone+Deserialize<'de>> Deserialize<'de> for HeapCell<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HeapCell<T>, D::Error> {
+        let nodes : Vec<(i32, T, Option<usize>, Option<usize>)> = Vec::deserialize(deserializer)?;
+        let mut has_parent = vec![false; nodes.len()];
+        for (i, &(_, _, left, right)) in nodes.iter().enumerate() {
+            for c in left.into_iter().chain(right) {
+                if c <= i || c >= nodes.len() || has_parent[c] {
+                    return Err(D::Error::custom("heap: invalid child index"))
+                }
+                has_parent[c] = true;
+            }
+        }
+        if has_parent.iter().skip(1).any(|p| !p) {
+            return Err(D::Error::custom("heap: a node has no parent"))
+        }
+        // built from the last node up, so that the children of a node are
+        // there when it is reached
+        let empty = Ref::new(HeapCell::Empty);
+        let mut cells : Vec<Option<Ref<HeapCell<T>>>> = nodes.iter().map(|_| None).collect();
+        for (i, (r, elt, left, right)) in nodes.into_iter().enumerate().rev() {
+            let left = match left {
+                Some(c) => cells[c].take().unwrap(),
+                None    => empty.clone()
+            };
+            let right = match right {
+                Some(c) => cells[c].take().unwrap(),
+                None    => empty.clone()
+            };
+            if root(&left).map_or(false, |x| *x < elt) || root(&right).map_or(false, |x| *x < elt) {
+                return Err(D::Error::custom("heap: a child is smaller than its parent"))
+            }
+            if rank(&left) < rank(&right) {
+                return Err(D::Error::custom("heap: the left child has a smaller rank than the right one"))
+            }
+            if r != rank(&right) + 1 {
+                return Err(D::Error::custom("heap: wrong rank"))
+            }
+            cells[i] = Some(Ref::new(HeapCell::Node { rank: r, elt: elt, left: left, right: right }));
+        }
+        match cells.into_iter().next() {
+            Some(Some(cell)) => Ok(cell.consume()),
+            _                => Ok(HeapCell::Empty)
+        }
+    }
+}
+
+/// Written in ascending order.
+impl <T: Ord+Clone+Serialize> Serialize for SkewHeap<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let xs : Vec<T> = self.iter().collect();
+        xs.serialize(serializer)
+    }
+}
+
+impl <'de, T: Ord+Clone+Deserialize<'de>> Deserialize<'de> for SkewHeap<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SkewHeap<T>, D::Error> {
+        Ok(SkewHeap::from_vec(Vec::deserialize(deserializer)?))
+    }
+}
+
+impl <T: Hash+Eq+Clone+Serialize> Serialize for HashSet<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, T: Hash+Eq+Clone+Deserialize<'de>> Deserialize<'de> for HashSet<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<HashSet<T>, D::Error> {
+        let xs : Vec<T> = Vec::deserialize(deserializer)?;
+        Ok(xs.into_iter().collect())
+    }
+}
+
+/// Written as `(element, count)` pairs.
+impl <T: Hash+Eq+Clone+Serialize> Serialize for Bag<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.distinct_len(), self.iter())
+    }
+}
+
+impl <'de, T: Hash+Eq+Clone+Deserialize<'de>> Deserialize<'de> for Bag<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Bag<T>, D::Error> {
+        let xs : Vec<(T, usize)> = Vec::deserialize(deserializer)?;
+        let mut ret = Bag::new();
+        for (x, n) in xs {
+            ret.insert_n(x, n);
+        }
+        Ok(ret)
+    }
+}
+
+/// Written as `(key, value)` pairs, in lexicographic order of the keys.
+impl <V: Clone+Serialize> Serialize for Trie<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, V: Clone+Deserialize<'de>> Deserialize<'de> for Trie<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Trie<V>, D::Error> {
+        let entries : Vec<(Vec<u8>, V)> = Vec::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+/// Written as `(range, value)` pairs, in order of the starts.
+impl <K: Ord+Clone+Serialize, V: Clone+Serialize> Serialize for IntervalTree<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), self.iter())
+    }
+}
+
+impl <'de, K, V> Deserialize<'de> for IntervalTree<K, V>
+    where K: Ord+Clone+Deserialize<'de>, V: Clone+Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<IntervalTree<K, V>, D::Error> {
+        let entries : Vec<(Range<K>, V)> = Vec::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+/// Written as the representative of each element.
+impl Serialize for UnionFind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.len(), (0..self.len()).map(|x| self.find(x)))
+    }
+}
+
+/// Fails unless every representative is an element that represents itself.
+impl <'de> Deserialize<'de> for UnionFind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<UnionFind, D::Error> {
+        let reps : Vec<usize> = Vec::deserialize(deserializer)?;
+        if reps.iter().any(|&r| r >= reps.len() || reps[r] != r) {
+            return Err(D::Error::custom("union find: invalid representative"))
+        }
+        let mut ret = UnionFind::new(reps.len());
+        for (x, &r) in reps.iter().enumerate() {
+            ret.union(x, r);
+        }
+        Ok(ret)
+    }
+}
+
+
+#[cfg(test)]
+fn round_trip<T: Serialize+for<'de> Deserialize<'de>>(x: &T) -> (T, T) {
+    let json = ::serde_json::to_string(x).unwrap();
+    let bytes = ::bincode::serialize(x).unwrap();
+    (::serde_json::from_str(&json).unwrap(), ::bincode::deserialize(&bytes).unwrap())
+}
+
+#[test]
+fn serde_sequences() {
+    let mut l = List::new();
+    let mut d = Deque::new();
+    let mut s = Stack::new();
+    for i in 0..100 {
+        l.prepend(i);
+        d.push_front(i);
+        s.push(i);
+    }
+    let mut small = List::new();
+    for i in 0..3 {
+        small.prepend(i);
+    }
+    assert_eq!("[2,1,0]", ::serde_json::to_string(&small).unwrap());
+
+    let (l1, l2) = round_trip(&l);
+    assert_eq!(l.iter().collect::<Vec<_>>(), l1.iter().collect::<Vec<_>>());
+    assert_eq!(l.iter().collect::<Vec<_>>(), l2.iter().collect::<Vec<_>>());
+    assert_eq!(100, l2.len());
+    let (d1, d2) = round_trip(&d);
+    assert_eq!(d.iter().collect::<Vec<_>>(), d1.iter().collect::<Vec<_>>());
+    assert_eq!(d.iter().collect::<Vec<_>>(), d2.iter().collect::<Vec<_>>());
+    let (s1, s2) = round_trip(&s);
+    assert_eq!(s.iter().collect::<Vec<_>>(), s1.iter().collect::<Vec<_>>());
+    assert_eq!(s.iter().collect::<Vec<_>>(), s2.iter().collect::<Vec<_>>());
+
+    let r = Rope::from("two\nlines");
+    let (r1, r2) = round_trip(&r);
+    assert_eq!("\"two\\nlines\"", ::serde_json::to_string(&r).unwrap());
+    assert_eq!(r.to_string(), r1.to_string());
+    assert_eq!(r.to_string(), r2.to_string());
+}
+
+#[test]
+fn serde_sets_and_maps() {
+    let h : HashSet<i32> = (0..50).collect();
+    let (h1, h2) = round_trip(&h);
+    assert_eq!(h, h1);
+    assert_eq!(h, h2);
+
+    let b : Bag<char> = "banana".chars().collect();
+    let (b1, b2) = round_trip(&b);
+    assert_eq!(b, b1);
+    assert_eq!(b, b2);
+
+    let t : Trie<i32> = vec![("a", 1), ("ab", 2), ("b", 3)].into_iter().collect();
+    let (t1, t2) = round_trip(&t);
+    assert_eq!(t.iter().collect::<Vec<_>>(), t1.iter().collect::<Vec<_>>());
+    assert_eq!(t.iter().collect::<Vec<_>>(), t2.iter().collect::<Vec<_>>());
+
+    let it : IntervalTree<i32, &str> = vec![(1..4, "a"), (2..3, "b")].into_iter().collect();
+    let json = ::serde_json::to_string(&it).unwrap();
+    let it1 : IntervalTree<i32, String> = ::serde_json::from_str(&json).unwrap();
+    assert_eq!(vec![(1..4, "a".to_string()), (2..3, "b".to_string())], it1.iter().collect::<Vec<_>>());
+
+    let mut uf = UnionFind::new(5);
+    uf.union(0, 3);
+    uf.union(3, 4);
+    let (uf1, uf2) = round_trip(&uf);
+    assert!(uf1.same_set(0, 4) && uf2.same_set(0, 4));
+    assert!(!uf1.same_set(0, 1));
+    assert_eq!(3, uf2.num_sets());
+    assert!(::serde_json::from_str::<UnionFind>("[1,2,2]").is_err());
+}
+
+#[test]
+fn serde_heaps() {
+    let h : Heap<i32> = vec![5, 3, 8, 1].into_iter().collect();
+    assert_eq!("[1,3,5,8]", ::serde_json::to_string(&h).unwrap());
+    let (h1, h2) = round_trip(&h);
+    assert_eq!(vec![1, 3, 5, 8], h1.iter().collect::<Vec<_>>());
+    assert_eq!(vec![1, 3, 5, 8], h2.iter().collect::<Vec<_>>());
+
+    let sh : SkewHeap<i32> = vec![5, 3, 8, 1].into_iter().collect();
+    let (sh1, _) = round_trip(&sh);
+    assert_eq!(vec![1, 3, 5, 8], sh1.iter().collect::<Vec<_>>());
+
+    let empty = Ref::new(HeapCell::new());
+    let cell = HeapCell::new().insert(2, &empty).insert(1, &empty).insert(3, &empty);
+    let (cell1, cell2) = round_trip(&cell);
+    assert_eq!(Some(1), cell1.pop_min().map(|(x, _)| x));
+    assert_eq!(Some(1), cell2.pop_min().map(|(x, _)| x));
+
+    // out of order
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[1,5,1,null],[1,2,null,null]]").is_err());
+    // wrong rank
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[2,1,1,null],[1,2,null,null]]").is_err());
+    // right child of higher rank than the left one
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[1,1,null,1],[1,2,null,null]]").is_err());
+    // not a tree
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[1,1,0,null]]").is_err());
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[1,1,1,null],[1,2,2,null],[1,3,null,null],[1,4,null,null]]").is_err());
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[[1,1,1,null],[1,2,null,null]]").is_ok());
+    assert!(::serde_json::from_str::<HeapCell<i32>>("[]").unwrap().is_empty());
+
+    // inserting in decreasing order makes a left spine as long as the heap,
+    // far deeper than serde_json nests
+    let mut spine = HeapCell::new();
+    for i in (0..1000).rev() {
+        spine = spine.insert(i, &empty);
+    }
+    let (spine1, spine2) = round_trip(&spine);
+    for (mut h, name) in vec![(spine1, "json"), (spine2, "bincode")] {
+        let mut n = 0;
+        while let Some((x, rest)) = h.pop_min() {
+            assert_eq!(n, x, "{}", name);
+            n += 1;
+            h = rest;
+        }
+        assert_eq!(1000, n);
+    }
+
+    // the empty children share one cell
+    let cell : HeapCell<i32> = ::serde_json::from_str("[[1,1,1,null],[1,2,null,null]]").unwrap();
+    match cell {
+        HeapCell::Node { ref left, ref right, .. } => match **left {
+            HeapCell::Node { right: ref empty, .. } => assert!(Rc::ptr_eq(&right._ptr, &empty._ptr)),
+            HeapCell::Empty                          => panic!("the left child is a node")
+        },
+        HeapCell::Empty => panic!("the heap is not empty")
+    }
+}
@@ -0,0 +1,92 @@
+// Helpers for the `Debug` and `Display` impls of the collections. The
+// compact forms go through the `debug_*` builders, with elements wrapped in
+// `Disp` for `Display`. The `{:#?}` forms that show the nodes are written
+// with `Dump`, which walks them without recursing, so that long lists do not
+// overflow the stack.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use refs::*;
+
+/// Formats `T` with `Display` where `Debug` is expected.
+pub struct Disp<T>(pub T);
+
+impl <T: fmt::Display> fmt::Debug for Disp<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Writes the nodes of a collection one per line, each under an id that the
+/// lines of the nodes pointing to it refer to. A node reached more than once
+/// is written once, with its strong count.
+pub struct Dump<'a, 'b: 'a> {
+    f       : &'a mut fmt::Formatter<'b>,
+    ids     : HashMap<usize, usize>,
+    written : HashSet<usize>,
+}
+
+impl <'a, 'b> Dump<'a, 'b> {
+    pub fn new(f: &'a mut fmt::Formatter<'b>, name: &str) -> Result<Dump<'a, 'b>, fmt::Error> {
+        writeln!(f, "{} {{", name)?;
+        Ok(Dump { f: f, ids: HashMap::new(), written: HashSet::new() })
+    }
+
+    fn addr<X: Clone>(r: &Ref<X>) -> usize {
+        &*r._ptr as *const X as usize
+    }
+
+    pub fn id<X: Clone>(&mut self, r: &Ref<X>) -> usize {
+        let next = self.ids.len();
+        *self.ids.entry(Dump::addr(r)).or_insert(next)
+    }
+
+    /// Starts the line of the node behind `r`, unless it was written before.
+    pub fn node<X: Clone>(&mut self, r: &Ref<X>) -> Result<bool, fmt::Error> {
+        if !self.written.insert(Dump::addr(r)) {
+            return Ok(false)
+        }
+        let id = self.id(r);
+        write!(self.f, "    #{} (rc {}): ", id, Rc::strong_count(&r._ptr))?;
+        Ok(true)
+    }
+
+    /// Writes a line, or the rest of the line a `node` started.
+    pub fn line(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.f.write_fmt(args)?;
+        self.f.write_str("\n")
+    }
+
+    pub fn field(&mut self, name: &str, value: &dyn fmt::Debug) -> fmt::Result {
+        writeln!(self.f, "    {}: {:?}", name, value)
+    }
+
+    pub fn finish(self) -> fmt::Result {
+        self.f.write_str("}")
+    }
+}
+
+/// Writes `Deque[1, 2 | 3, 4]`, the front part of a deque before the bar and
+/// its back part after it, both in order. The bar is left out when a part is
+/// empty, as in `Deque[1]` and `Deque[]`.
+pub fn deque_parts<T, F>(f: &mut fmt::Formatter, front: &[&T], back: &[&T], each: F) -> fmt::Result
+    where F: Fn(&T, &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("Deque[")?;
+    for (i, &x) in front.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        each(x, f)?;
+    }
+    if !front.is_empty() && !back.is_empty() {
+        f.write_str(" | ")?;
+    }
+    for (i, &x) in back.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        each(x, f)?;
+    }
+    f.write_str("]")
+}
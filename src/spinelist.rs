@@ -1,10 +1,22 @@
-pub use giftr::refs::functional::Ref as Ref;
+pub use refs::Ref;
+#[cfg(not(feature = "sync"))]
 use giftr::ispine::*;
+#[cfg(not(feature = "sync"))]
 use giftr::ispine::contiguous::Contiguous as Spine;
+#[cfg(feature = "sync")]
+use self::sync::Contiguous as Spine;
 use std::default::Default;
+use std::fmt;
 use std::fmt::Debug;
+use std::mem::size_of;
 
-#[derive(Clone, Debug)]
+use show::Disp;
+use diff::{Diff, Edit, PatchError, script, patch_seq};
+use cursor::{self, StackSeq};
+use stats::{MemoryStats, NodeVisitor};
+use dot::{ToDot, Graph};
+
+#[derive(Clone)]
 pub struct SpineList<T: Clone> {
     spine : Spine<T>,
 }
@@ -32,12 +44,7 @@ impl <T: Clone+Debug> SpineList<T> {
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        if let Some(x) = self.spine.pop() {
-            println!("popped {:?}, len was {}", x, self.len());
-            Some(x)
-        } else {
-            None
-        }
+        self.spine.pop()
     }
 
     pub fn pop_back(&mut self) -> Option<T> {
@@ -53,6 +60,10 @@ impl <T: Clone+Debug> SpineList<T> {
         self.spine.iter().count()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.spine.iter().next().is_none()
+    }
+
     pub fn iter(&self) -> Iter<T> {
         Iter { cur: self.spine.clone() }
     }
@@ -61,6 +72,271 @@ impl <T: Clone+Debug> SpineList<T> {
         let SpineList { spine } = self;
         Iter { cur: spine }
     }
+
+    /// A cursor on the first element. Walking it does not affect `self`.
+    pub fn cursor(&self) -> Cursor<T> {
+        Cursor::new(self.clone())
+    }
+
+    /// A transient holding the elements of `self`, to build on it without
+    /// walking the spine at every append.
+    pub fn transient(self) -> TransientSpineList<T> {
+        TransientSpineList { list: self, tail: vec![] }
+    }
+
+    /// A cursor on the first element whose edits are written back to `self`
+    /// when it is dropped. Unlike `insert`, edits at the cursor do not walk
+    /// the spine from the start.
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut::new(self)
+    }
+}
+
+/// A zipper over a `SpineList`, see `cursor::Cursor`.
+pub type Cursor<T> = cursor::Cursor<SpineList<T>>;
+
+/// A `Cursor` that can edit the list it was made from.
+pub type CursorMut<'a, T> = cursor::CursorMut<'a, SpineList<T>>;
+
+impl <T: Clone+Debug> StackSeq for SpineList<T> {
+    type Elt = T;
+
+    fn empty() -> SpineList<T> {
+        SpineList::new()
+    }
+
+    fn push_front(&mut self, x: T) {
+        self.prepend(x)
+    }
+
+    fn pop_front(&mut self) -> Option<T> {
+        SpineList::pop_front(self)
+    }
+}
+
+/// A `SpineList` being built in place, from `SpineList::transient`.
+/// Prepends go straight to the spine, while appends, which would each walk
+/// it, are kept aside until `persistent` adds them in one walk.
+pub struct TransientSpineList<T: Clone> {
+    list : SpineList<T>,
+    // appended elements, not added to the spine yet
+    tail : Vec<T>,
+}
+
+impl <T: Clone+Debug> TransientSpineList<T> {
+    pub fn len(&self) -> usize {
+        self.list.len() + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty() && self.tail.is_empty()
+    }
+
+    pub fn prepend(&mut self, x: T) {
+        self.list.prepend(x)
+    }
+
+    pub fn append(&mut self, x: T) {
+        self.tail.push(x)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        match self.list.pop_front() {
+            Some(x) => Some(x),
+            None    => {
+                self.link();
+                self.list.pop_front()
+            }
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if let Some(x) = self.tail.pop() {
+            return Some(x)
+        }
+        // `SpineList::pop_back` does not handle the empty list
+        if self.list.is_empty() {
+            return None
+        }
+        self.list.pop_back()
+    }
+
+    /// Freezes the transient into a list.
+    pub fn persistent(mut self) -> SpineList<T> {
+        self.link();
+        self.list
+    }
+
+    // Adds the appended elements after the last one, through the position
+    // of the spine for it, found in one walk.
+    fn link(&mut self) {
+        if self.tail.is_empty() {
+            return
+        }
+        let spine = &mut self.list.spine;
+        if let Some(mut last) = spine.at().last() {
+            // each one goes right after the last element, before those
+            // added so far
+            for x in self.tail.drain(..).rev() {
+                last.insert(x);
+            }
+            return
+        }
+        for x in self.tail.drain(..).rev() {
+            spine.add(x);
+        }
+    }
+}
+
+// The spine lives in giftr, out of reach, so the elements are compared one
+// by one.
+impl <T: Clone+Debug+PartialEq> Diff for SpineList<T> {
+    type Elt = T;
+
+    fn diff(&self, other: &SpineList<T>) -> Vec<Edit<T>> {
+        let (old, new) : (Vec<T>, Vec<T>) = (self.iter().collect(), other.iter().collect());
+        let old : Vec<&T> = old.iter().collect();
+        let new : Vec<&T> = new.iter().collect();
+        script(&old, &new, 0)
+    }
+
+    fn patch(&mut self, edits: &[Edit<T>]) -> Result<(), PatchError> {
+        patch_seq(self, edits)
+    }
+}
+
+// The spine lives in giftr, which does not tell how its nodes are shared:
+// each element counts as an owned node of its own size.
+impl <T: Clone+Debug> MemoryStats for SpineList<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        let len = self.len();
+        v.opaque_nodes(len, len * size_of::<T>())
+    }
+}
+
+// The spine is out of reach as well, so the elements are drawn as a chain of
+// records, and versions that share part of their spine do not share it in
+// the graph.
+impl <T: Clone+Debug> ToDot for SpineList<T> {
+    fn to_dot(&self, g: &mut Graph) -> String {
+        let mut iter = self.iter();
+        let first = match iter.next() {
+            Some(x) => g.record(&[format!("{:?}", x)]),
+            None    => return g.record(&["nil".to_string()])
+        };
+        let mut prev = first.clone();
+        for x in iter {
+            let id = g.record(&[format!("{:?}", x)]);
+            g.edge(&prev, &id, "");
+            prev = id;
+        }
+        first
+    }
+}
+
+/// `[1, 2, 3]`. The spine lives in giftr, so `{:#?}` only lays the
+/// elements out one per line.
+impl <T: Clone+Debug> Debug for SpineList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl <T: Clone+Debug+fmt::Display> fmt::Display for SpineList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+// giftr's spines are built on `Rc`. With the `sync` feature the list uses
+// this one instead: the same interface over a `List`, whose nodes sit behind
+// the `Arc`-based `Ref`. Clones share the nodes, and a change copies only the
+// ones in front of it, as giftr's spine does.
+#[cfg(feature = "sync")]
+mod sync {
+    use std::cell::RefCell;
+    use std::default::Default;
+    use std::rc::Rc;
+    use list::{List, Elts, CursorMut};
+
+    #[derive(Clone)]
+    pub struct Contiguous<T: Clone> {
+        list : List<T>,
+    }
+
+    impl <T: Clone> Default for Contiguous<T> {
+        fn default() -> Contiguous<T> {
+            Contiguous { list: List::new() }
+        }
+    }
+
+    impl <T: Clone> Contiguous<T> {
+        pub fn add(&mut self, x: T) {
+            self.list.prepend(x)
+        }
+
+        pub fn pop(&mut self) -> Option<T> {
+            self.list.pop_front()
+        }
+
+        /// The positions of the elements, from the front. They share one
+        /// cursor, which writes the list back once they are all dropped.
+        pub fn at(&mut self) -> At<'_, T> {
+            At { cursor: Rc::new(RefCell::new(self.list.cursor_mut())), idx: 0 }
+        }
+
+        /// Keeps the first `idx` elements and returns the others.
+        pub fn take_from(&mut self, idx: usize) -> Contiguous<T> {
+            let mut cursor = self.list.cursor_mut();
+            for _ in 0..idx {
+                cursor.move_next();
+            }
+            Contiguous { list: cursor.split() }
+        }
+
+        pub fn iter(&self) -> Elts<'_, T> {
+            self.list.elts()
+        }
+    }
+
+    pub struct At<'a, T: Clone+'a> {
+        cursor : Rc<RefCell<CursorMut<'a, T>>>,
+        idx    : usize,
+    }
+
+    impl <'a, T: Clone> Iterator for At<'a, T> {
+        type Item = Pos<'a, T>;
+        fn next(&mut self) -> Option<Pos<'a, T>> {
+            if !seek(&self.cursor, self.idx) {
+                return None
+            }
+            self.idx += 1;
+            Some(Pos { cursor: self.cursor.clone(), idx: self.idx - 1 })
+        }
+    }
+
+    // Moves the cursor onto the `idx`th element, false if there is none.
+    fn seek<'a, T: Clone>(cursor: &RefCell<CursorMut<'a, T>>, idx: usize) -> bool {
+        let mut cursor = cursor.borrow_mut();
+        while cursor.index() > idx {
+            cursor.move_prev();
+        }
+        while cursor.index() < idx && cursor.move_next() {}
+        cursor.index() == idx && cursor.current().is_some()
+    }
+
+    /// The position of an element, to insert after it.
+    pub struct Pos<'a, T: Clone+'a> {
+        cursor : Rc<RefCell<CursorMut<'a, T>>>,
+        idx    : usize,
+    }
+
+    impl <'a, T: Clone> Pos<'a, T> {
+        pub fn insert(&mut self, x: T) {
+            seek(&self.cursor, self.idx);
+            self.cursor.borrow_mut().insert_after(x)
+        }
+    }
 }
 
 pub struct Iter<T: Clone> {
@@ -75,7 +351,6 @@ impl <T: Clone> Iterator for Iter<T> {
 }
 
 mod test {
-    use giftr::refs::*;
     use super::{Ref, SpineList};
     #[test]
     fn lst_len() {
@@ -143,6 +418,68 @@ mod test {
         assert_eq!(None, lst.pop_front());
     }
 
+    #[test]
+    fn lst_cursor() {
+        let mut lst = SpineList::new();
+        lst.prepend(3);
+        lst.prepend(2);
+        lst.prepend(1);
+
+        let mut c = lst.cursor();
+        assert_eq!(Some(&1), c.current());
+        assert!(!c.move_prev());
+        assert!(c.move_next());
+        assert!(c.move_next());
+        assert_eq!(Some(&3), c.current());
+        assert!(c.move_next());
+        assert_eq!(None, c.current());
+        assert_eq!(3, c.index());
+        assert!(!c.move_next());
+        assert!(c.move_prev());
+        assert!(c.move_prev());
+        assert_eq!(Some(&2), c.current());
+        assert_eq!(1, c.index());
+
+        let (prefix, suffix) = c.split();
+        assert_eq!(vec![1], prefix.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2, 3], suffix.iter().collect::<Vec<_>>());
+        assert_eq!(vec![1, 2, 3], lst.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lst_cursor_mut() {
+        let mut lst = SpineList::new();
+        lst.prepend(4);
+        lst.prepend(2);
+        lst.prepend(1);
+        let snapshot = lst.clone();
+        {
+            let mut c = lst.cursor_mut();
+            c.move_next();
+            c.insert_after(3);
+            c.insert_before(0);
+            assert_eq!(Some(&2), c.current());
+            assert_eq!(2, c.index());
+            c.move_next();
+            assert_eq!(Some(3), c.remove_current());
+            assert_eq!(Some(&4), c.current());
+            c.move_next();
+            c.insert_after(5);
+            assert_eq!(Some(&5), c.current());
+        }
+        assert_eq!(vec![1, 0, 2, 4, 5], lst.iter().collect::<Vec<_>>());
+        assert_eq!(vec![1, 2, 4], snapshot.iter().collect::<Vec<_>>());
+
+        let tail = {
+            let mut c = lst.cursor_mut();
+            c.move_next();
+            c.move_next();
+            c.split()
+        };
+        assert_eq!(vec![1, 0], lst.iter().collect::<Vec<_>>());
+        assert_eq!(vec![2, 4, 5], tail.iter().collect::<Vec<_>>());
+    }
+
     #[test]
     fn lst_copy() {
         println!("=== LST_COPY ==============");
@@ -159,6 +496,63 @@ mod test {
         assert!(2 == lst2.len());
     }
 
+    #[test]
+    fn lst_diff() {
+        use diff::{Diff, Edit};
+        let mut base = SpineList::new();
+        for i in 0..5 {
+            base.append(i);
+        }
+        let mut ours = base.clone();
+        {
+            let mut c = ours.cursor_mut();
+            c.move_next();
+            c.move_next();
+            c.insert_before(10);
+        }
+        ours.pop_back();
+        let edits = base.diff(&ours);
+        assert_eq!(vec![Edit::Insert(2, 10), Edit::Delete(5)], edits);
+        let mut theirs = base.clone();
+        theirs.patch(&edits).unwrap();
+        assert_eq!(vec![0, 1, 10, 2, 3], theirs.iter().collect::<Vec<_>>());
+        assert!(theirs.patch(&[Edit::Replace(5, 0)]).is_err());
+        assert_eq!(5, theirs.len());
+    }
+
+    #[test]
+    fn lst_transient() {
+        let mut base = SpineList::new();
+        base.append(1);
+        let mut t = base.clone().transient();
+        for i in 2..100 {
+            t.append(i);
+        }
+        t.prepend(0);
+        assert_eq!(100, t.len());
+        assert_eq!(Some(99), t.pop_back());
+        let lst = t.persistent();
+        assert_eq!((0..99).collect::<Vec<_>>(), lst.iter().collect::<Vec<_>>());
+        assert_eq!(vec![1], base.iter().collect::<Vec<_>>());
+
+        let mut t = SpineList::new().transient();
+        t.append(1);
+        t.append(2);
+        assert_eq!(Some(1), t.pop_front());
+        assert_eq!(vec![2], t.persistent().iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn lst_fmt() {
+        let mut lst = SpineList::new();
+        assert_eq!("[]", format!("{:?}", lst));
+        lst.prepend("b");
+        lst.prepend("a");
+        assert_eq!("[\"a\", \"b\"]", format!("{:?}", lst));
+        assert_eq!("[a, b]", format!("{}", lst));
+        assert_eq!("[\n    \"a\",\n    \"b\",\n]", format!("{:#?}", lst));
+    }
+
     #[test]
     fn lst_iter() {
         let mut lst1 = Ref::new(SpineList::new());
@@ -174,13 +568,59 @@ mod test {
         }
     }
 
+    #[cfg(feature = "sync")]
+    #[test]
+    fn lst_threads() {
+        use std::thread;
+        let mut lst = SpineList::new();
+        for i in 0..1000 {
+            lst.prepend(i);
+        }
+        let workers : Vec<_> = (0..4).map(|t| {
+            let mut mine = lst.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    mine.prepend(t * 1000 + i);
+                }
+                mine.insert(0, -1);
+                mine.pop_back();
+                (mine.len(), mine.pop_front(), mine.pop_front())
+            })
+        }).collect();
+        for (t, worker) in workers.into_iter().enumerate() {
+            assert_eq!((1100, Some(t as i32 * 1000 + 99), Some(-1)), worker.join().unwrap());
+        }
+        assert_eq!(1000, lst.len());
+        assert_eq!(Some(0), lst.pop_back());
+    }
+
+    #[test]
+    fn lst_shared_edits() {
+        let mut lst = SpineList::new();
+        for i in (0..100).rev() {
+            lst.prepend(i);
+        }
+        let mut mine = lst.clone();
+        mine.prepend(-1);
+        mine.insert(50, 1000);
+        mine.append(2000);
+        assert_eq!(Some(2000), mine.pop_back());
+        let mut t = mine.transient();
+        for i in 0..10 {
+            t.append(3000 + i);
+        }
+        let mine = t.persistent();
+        let expected : Vec<i32> = vec![-1].into_iter().chain(0..50).chain(vec![1000]).chain(50..100).chain(3000..3010).collect();
+        assert_eq!(expected, mine.iter().collect::<Vec<_>>());
+        assert_eq!((0..100).collect::<Vec<_>>(), lst.iter().collect::<Vec<_>>());
+    }
+
 }
 
 #[cfg(test)]
 mod bench {
     use test;
     use test::Bencher;
-    use giftr::refs::*;
     use super::{Ref, SpineList};
 
     #[bench]
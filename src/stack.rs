@@ -0,0 +1,311 @@
+use refs::*;
+use archive::{Archivable, Archive, ArchiveReader, ArchiveError, Link};
+use stats::{MemoryStats, NodeVisitor};
+use show::{Disp, Dump};
+use std::fmt;
+use std::iter::Iterator;
+
+#[derive(Clone)]
+struct Node<T: Clone> {
+    elt  : T,
+    // the number of elements from this node down, so that `len` is O(1) and
+    // versions can be aligned when looking for a shared tail
+    len  : usize,
+    next : Option<Ref<Node<T>>>,
+}
+
+/// A persistent stack. All operations are O(1); cloning a stack shares all of
+/// its nodes, and stacks derived from one another share their tails.
+#[derive(Clone)]
+pub struct Stack<T: Clone> {
+    top : Option<Ref<Node<T>>>,
+}
+
+impl <T: Clone> Stack<T> {
+    pub fn new() -> Stack<T> {
+        Stack { top: None }
+    }
+
+    pub fn push(&mut self, x: T) {
+        let len = self.len() + 1;
+        let next = self.top.take();
+        self.top = Some(Ref::new(Node { elt: x, len: len, next: next }));
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.top.take().map(|top| {
+            let mut node = top.consume();
+            self.top = node.next.take();
+            node.elt
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.top.as_ref().map(|top| &top.elt)
+    }
+
+    pub fn len(&self) -> usize {
+        self.top.as_ref().map_or(0, |top| top.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top.is_none()
+    }
+
+    /// Iterates from the top of the stack down.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { cur: self.top.as_ref().map(|top| &**top) }
+    }
+
+    /// The number of nodes at the bottom of the stacks that `self` and
+    /// `other` physically share. Runs in O(n), where n is the number of
+    /// elements that are not shared.
+    pub fn common_suffix_len(&self, other: &Stack<T>) -> usize {
+        let mut a = self.top.as_ref();
+        let mut b = other.top.as_ref();
+        while Stack::depth(a) > Stack::depth(b) {
+            a = a.and_then(|node| node.next.as_ref());
+        }
+        while Stack::depth(b) > Stack::depth(a) {
+            b = b.and_then(|node| node.next.as_ref());
+        }
+        while let (Some(x), Some(y)) = (a, b) {
+            if Rc::ptr_eq(&x._ptr, &y._ptr) {
+                return x.len
+            }
+            a = x.next.as_ref();
+            b = y.next.as_ref();
+        }
+        0
+    }
+
+    /// Whether `self` and `other` share any nodes.
+    pub fn shares_tail_with(&self, other: &Stack<T>) -> bool {
+        self.common_suffix_len(other) > 0
+    }
+
+    fn depth(node: Option<&Ref<Node<T>>>) -> usize {
+        node.map_or(0, |node| node.len)
+    }
+}
+
+impl <T: Clone> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let mut optcur = self.top.take();
+        while let Some(cur) = optcur {
+            match Rc::try_unwrap(cur._ptr) {
+                Ok(mut cur) => optcur = cur.next.take(),
+                Err(_)      => break
+            }
+        }
+    }
+}
+
+/// `Stack[3, 2, 1]`, from the top down, or with `{:#?}` the nodes with
+/// their strong counts.
+impl <T: Clone+fmt::Debug> fmt::Debug for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            f.write_str("Stack")?;
+            return f.debug_list().entries(self.iter()).finish()
+        }
+        let mut d = Dump::new(f, "Stack")?;
+        let mut cur = self.top.as_ref();
+        while let Some(node) = cur {
+            if !d.node(node)? {
+                break
+            }
+            match node.next {
+                Some(ref next) => {
+                    let id = d.id(next);
+                    d.line(format_args!("{:?} -> #{}", node.elt, id))?
+                }
+                None => d.line(format_args!("{:?}", node.elt))?
+            }
+            cur = node.next.as_ref();
+        }
+        d.finish()
+    }
+}
+
+impl <T: Clone+fmt::Display> fmt::Display for Stack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Stack")?;
+        f.debug_list().entries(self.iter().map(Disp)).finish()
+    }
+}
+
+impl <T: Clone> MemoryStats for Stack<T> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        let mut cur = self.top.as_ref();
+        while let Some(node) = cur {
+            if !v.node_ref(node, 0) {
+                return
+            }
+            cur = node.next.as_ref();
+        }
+    }
+}
+
+// Written from the top down until a node the archive already holds, so that
+// stacks sharing a tail write it once.
+impl <T: Clone+Archivable+'static> Archivable for Stack<T> {
+    fn archive(&self, a: &mut Archive) {
+        let mut cur = self.top.as_ref();
+        while let Some(node) = cur {
+            if !a.begin_node(node) {
+                return
+            }
+            node.elt.archive(a);
+            cur = node.next.as_ref();
+        }
+        a.write_none()
+    }
+
+    fn unarchive(r: &mut ArchiveReader) -> Result<Stack<T>, ArchiveError> {
+        let mut fresh = vec![];
+        let mut top = loop {
+            match r.read_link()? {
+                Link::None         => break None,
+                Link::Shared(node) => break Some(node),
+                Link::New(id)      => fresh.push((id, T::unarchive(r)?))
+            }
+        };
+        for (id, elt) in fresh.into_iter().rev() {
+            let len = Stack::depth(top.as_ref()) + 1;
+            let node = Ref::new(Node { elt: elt, len: len, next: top });
+            r.fill_node(id, &node);
+            top = Some(node);
+        }
+        Ok(Stack { top: top })
+    }
+}
+
+pub struct Iter<'a, T: Clone+'a> {
+    cur: Option<&'a Node<T>>,
+}
+
+impl <'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.cur.map(|node| {
+            self.cur = node.next.as_ref().map(|next| &**next);
+            &node.elt
+        })
+    }
+}
+
+
+#[test]
+fn stack_push_pop() {
+    let mut s = Stack::new();
+    assert_eq!(None, s.peek());
+    s.push(1);
+    s.push(2);
+    s.push(3);
+    assert_eq!(3, s.len());
+    assert_eq!(Some(&3), s.peek());
+    assert_eq!(vec![&3, &2, &1], s.iter().collect::<Vec<_>>());
+
+    assert_eq!(Some(3), s.pop());
+    assert_eq!(Some(2), s.pop());
+    assert_eq!(Some(1), s.pop());
+    assert_eq!(None, s.pop());
+    assert_eq!(0, s.len());
+    assert!(s.is_empty());
+}
+
+#[test]
+fn stack_persistent() {
+    let mut s1 = Stack::new();
+    s1.push(1);
+    s1.push(2);
+    let mut s2 = s1.clone();
+    s2.pop();
+    s2.push(3);
+    s1.push(4);
+
+    assert_eq!(vec![&4, &2, &1], s1.iter().collect::<Vec<_>>());
+    assert_eq!(vec![&3, &1], s2.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn stack_sharing() {
+    let mut base = Stack::new();
+    for i in 0..10 {
+        base.push(i);
+    }
+    let mut s1 = base.clone();
+    let mut s2 = base.clone();
+    s1.push(10);
+    s1.push(11);
+    s2.pop();
+    s2.push(20);
+
+    assert_eq!(10, s1.common_suffix_len(&base));
+    assert_eq!(9, s1.common_suffix_len(&s2));
+    assert_eq!(9, s2.common_suffix_len(&s1));
+    assert!(s1.shares_tail_with(&s2));
+
+    // equal elements are not enough, the nodes have to be shared
+    let mut s3 = Stack::new();
+    for i in 0..10 {
+        s3.push(i);
+    }
+    assert_eq!(0, s3.common_suffix_len(&base));
+    assert!(!s3.shares_tail_with(&base));
+    assert_eq!(0, s3.common_suffix_len(&Stack::new()));
+}
+
+#[test]
+fn stack_archive() {
+    use archive::{Archive, ArchiveReader};
+    let mut base = Stack::new();
+    for i in 0..10 {
+        base.push(i);
+    }
+    let mut s1 = base.clone();
+    s1.push(10);
+    let mut s2 = base.clone();
+    s2.pop();
+    s2.push(20);
+
+    let mut a = Archive::new();
+    a.push(&s1);
+    a.push(&s2);
+    a.push(&base);
+    let bytes = a.into_bytes();
+    let mut r = ArchiveReader::new(&bytes).unwrap();
+    let (l1, l2, lbase) : (Stack<i32>, Stack<i32>, Stack<i32>) =
+        (r.read().unwrap(), r.read().unwrap(), r.read().unwrap());
+
+    assert_eq!(s1.iter().collect::<Vec<_>>(), l1.iter().collect::<Vec<_>>());
+    assert_eq!(s2.iter().collect::<Vec<_>>(), l2.iter().collect::<Vec<_>>());
+    assert_eq!(11, l1.len());
+    assert_eq!(10, l1.common_suffix_len(&lbase));
+    assert_eq!(9, l1.common_suffix_len(&l2));
+}
+
+#[test]
+fn stack_fmt() {
+    let mut s = Stack::new();
+    s.push("a");
+    s.push("b");
+    assert_eq!("Stack[\"b\", \"a\"]", format!("{:?}", s));
+    assert_eq!("Stack[b, a]", format!("{}", s));
+    assert_eq!("Stack {
+    #0 (rc 1): \"b\" -> #1
+    #1 (rc 1): \"a\"
+}", format!("{:#?}", s));
+}
+
+#[test]
+fn stack_drop_long() {
+    let mut s = Stack::new();
+    for i in 0..1000000 {
+        s.push(i);
+    }
+    let s2 = s.clone();
+    drop(s);
+    assert_eq!(1000000, s2.len());
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+
+use refs::*;
+
+/// Memory figures for the nodes of a collection value. A node counts as
+/// shared when its strong count is above one, which means another version,
+/// or another node of this one, also points to it, and as owned otherwise.
+/// Nodes met more than once are counted once.
+///
+/// Bytes are those of the nodes, their reference counts and the vectors
+/// they own; what the elements themselves own on the heap is not counted,
+/// except for the text of a `Rope`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub node_count        : usize,
+    /// The nodes whose strong count is one.
+    pub unique_node_count : usize,
+    pub shared_bytes      : usize,
+    pub owned_bytes       : usize,
+}
+
+impl Stats {
+    pub fn total_bytes(&self) -> usize {
+        self.shared_bytes + self.owned_bytes
+    }
+}
+
+/// Collections whose nodes can be inspected. The spine of a `SpineList` is
+/// kept inside giftr, so it reports one owned node per element, see
+/// `NodeVisitor::opaque_nodes`.
+pub trait MemoryStats {
+    /// Passes the nodes reachable from `self` to `v`.
+    fn visit_nodes(&self, v: &mut NodeVisitor);
+
+    fn stats(&self) -> Stats {
+        let mut v = NodeVisitor::new();
+        self.visit_nodes(&mut v);
+        v.stats
+    }
+}
+
+/// The memory used by a set of versions together: nodes that they share are
+/// counted once.
+pub fn total_memory<'a, C: MemoryStats+'a, I: IntoIterator<Item=&'a C>>(versions: I) -> usize {
+    let mut v = NodeVisitor::new();
+    for c in versions {
+        c.visit_nodes(&mut v);
+    }
+    v.stats.total_bytes()
+}
+
+/// Gathers `Stats` from the nodes of one or more collections.
+pub struct NodeVisitor {
+    seen  : HashSet<usize>,
+    stats : Stats,
+}
+
+// The strong and weak counts in front of every `Rc` and `Arc` allocation.
+const COUNTS : usize = 2 * size_of::<usize>();
+
+impl NodeVisitor {
+    fn new() -> NodeVisitor {
+        NodeVisitor { seen: HashSet::new(), stats: Stats::default() }
+    }
+
+    /// Records the node `x` behind a reference-counted pointer with the
+    /// given strong count, and `extra` bytes it owns besides. Returns false
+    /// if the node was visited before, in which case the caller skips what
+    /// it points to.
+    pub fn node<X>(&mut self, x: &X, strong_count: usize, extra: usize) -> bool {
+        if !self.seen.insert(x as *const X as usize) {
+            return false
+        }
+        let bytes = COUNTS + size_of::<X>() + extra;
+        self.stats.node_count += 1;
+        if strong_count == 1 {
+            self.stats.unique_node_count += 1;
+            self.stats.owned_bytes += bytes;
+        } else {
+            self.stats.shared_bytes += bytes;
+        }
+        true
+    }
+
+    pub fn node_ref<X: Clone>(&mut self, r: &Ref<X>, extra: usize) -> bool {
+        self.node(&*r._ptr, Rc::strong_count(&r._ptr), extra)
+    }
+
+    /// Records `count` nodes taking `bytes` bytes in all, whose pointers are
+    /// out of reach. They are counted as owned, and once for every version
+    /// they are met in.
+    pub fn opaque_nodes(&mut self, count: usize, bytes: usize) {
+        self.stats.node_count += count;
+        self.stats.unique_node_count += count;
+        self.stats.owned_bytes += bytes;
+    }
+}
+
+/// The bytes of the buffer of `v`.
+pub fn vec_bytes<T>(v: &Vec<T>) -> usize {
+    v.capacity() * size_of::<T>()
+}
+
+
+#[test]
+fn stats_list() {
+    use list::List;
+    let mut base = List::new();
+    for i in 0..100 {
+        base.prepend(i);
+    }
+    let s = base.stats();
+    assert_eq!(100, s.node_count);
+    assert_eq!(100, s.unique_node_count);
+    assert_eq!(0, s.shared_bytes);
+
+    let mut v = base.clone();
+    v.prepend(100);
+    let s = v.stats();
+    assert_eq!(101, s.node_count);
+    // only the first node of `base` is pointed to by both versions
+    assert_eq!(100, s.unique_node_count);
+    assert_eq!(100 * s.shared_bytes, s.owned_bytes);
+    assert_eq!(s.total_bytes(), total_memory(&[base.clone(), v.clone()]));
+}
+
+#[test]
+fn stats_versions() {
+    use hashset::HashSet;
+    use heap::Heap;
+    use union_find::UnionFind;
+
+    let h1 : Heap<i32> = (0..1000).collect();
+    let mut h2 = h1.clone();
+    h2.insert(-1);
+    let separate = h1.stats().total_bytes() + h2.stats().total_bytes();
+    assert!(total_memory(&[h1.clone(), h2.clone()]) < separate);
+
+    let s1 : HashSet<i32> = (0..1000).collect();
+    let mut s2 = s1.clone();
+    s2.insert(1000);
+    assert!(s2.stats().shared_bytes > 0);
+    assert!(total_memory(&[s1.clone(), s2.clone()]) < s1.stats().total_bytes() * 2);
+
+    let mut uf = UnionFind::new(100);
+    let snapshot = uf.clone();
+    uf.union(1, 2);
+    let s = uf.stats();
+    assert!(s.shared_bytes > 0);
+    // the snapshot reaches the new arrays through its differences, but not
+    // the other way around
+    let both = total_memory(&[uf.clone(), snapshot.clone()]);
+    assert!(both > s.total_bytes());
+    assert_eq!(both, snapshot.stats().total_bytes());
+}
+
+#[test]
+fn stats_spinelist() {
+    use spinelist::SpineList;
+    let mut l = SpineList::new();
+    for i in 0..100u64 {
+        l.prepend(i);
+    }
+    let s = l.stats();
+    assert_eq!(100, s.node_count);
+    assert_eq!(100, s.unique_node_count);
+    assert_eq!(100 * size_of::<u64>(), s.owned_bytes);
+    assert_eq!(2 * s.total_bytes(), total_memory(&[l.clone(), l.clone()]));
+}
@@ -0,0 +1,330 @@
+use refs::*;
+use std::ascii::escape_default;
+use std::fmt;
+use std::iter::{Iterator, FromIterator};
+use std::mem::replace;
+use std::str;
+
+use stats::{MemoryStats, NodeVisitor, vec_bytes};
+use show::Disp;
+
+#[derive(Clone, Debug)]
+struct Node<V: Clone> {
+    value    : Option<V>,
+    // sorted by byte, so that iteration yields keys in lexicographic order
+    children : Vec<(u8, Ref<Node<V>>)>,
+}
+
+impl <V: Clone> Node<V> {
+    fn new() -> Node<V> {
+        Node { value: None, children: vec![] }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.is_empty()
+    }
+
+    fn child(&self, b: u8) -> Option<&Node<V>> {
+        self.children.binary_search_by_key(&b, |c| c.0).ok().map(|i| &*self.children[i].1)
+    }
+
+    fn find(&self, key: &[u8]) -> Option<&Node<V>> {
+        let mut node = self;
+        for &b in key {
+            node = match node.child(b) {
+                Some(child) => child,
+                None        => return None
+            };
+        }
+        Some(node)
+    }
+}
+
+// Writing through a shared `Ref` copies the node, so that updates copy the
+// path from the root to the key and share everything else.
+fn insert<V: Clone>(node: &mut Ref<Node<V>>, key: &[u8], v: V) -> Option<V> {
+    let node = &mut **node;
+    match key.split_first() {
+        None => replace(&mut node.value, Some(v)),
+        Some((&b, rest)) => {
+            match node.children.binary_search_by_key(&b, |c| c.0) {
+                Ok(i)  => insert(&mut node.children[i].1, rest, v),
+                Err(i) => {
+                    let mut child = Ref::new(Node::new());
+                    insert(&mut child, rest, v);
+                    node.children.insert(i, (b, child));
+                    None
+                }
+            }
+        }
+    }
+}
+
+// Prunes the nodes left without values below them. The key must be present,
+// or the path to it would be copied for nothing.
+fn remove<V: Clone>(node: &mut Ref<Node<V>>, key: &[u8]) -> Option<V> {
+    let node = &mut **node;
+    match key.split_first() {
+        None => node.value.take(),
+        Some((&b, rest)) => {
+            let i = node.children.binary_search_by_key(&b, |c| c.0).ok()?;
+            let ret = remove(&mut node.children[i].1, rest);
+            if node.children[i].1.is_empty() {
+                node.children.remove(i);
+            }
+            ret
+        }
+    }
+}
+
+/// A persistent map from byte strings to values, which supports prefix
+/// queries. Operations take O(k) for keys of length k, and clones are O(1):
+/// versions share all nodes but the paths to the keys they changed. Keys can
+/// be anything that is `AsRef<[u8]>`, such as `&str` and `&[u8]`.
+#[derive(Clone)]
+pub struct Trie<V: Clone> {
+    root : Ref<Node<V>>,
+    len  : usize,
+}
+
+impl <V: Clone> Trie<V> {
+    pub fn new() -> Trie<V> {
+        Trie { root: Ref::new(Node::new()), len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value previously stored under `key`.
+    pub fn insert<K: AsRef<[u8]>>(&mut self, key: K, v: V) -> Option<V> {
+        let ret = insert(&mut self.root, key.as_ref(), v);
+        if ret.is_none() {
+            self.len += 1;
+        }
+        ret
+    }
+
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&V> {
+        self.root.find(key.as_ref()).and_then(|node| node.value.as_ref())
+    }
+
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<K: AsRef<[u8]>>(&mut self, key: K) -> Option<V> {
+        let key = key.as_ref();
+        if !self.contains_key(key) {
+            return None
+        }
+        self.len -= 1;
+        remove(&mut self.root, key)
+    }
+
+    /// Iterates over the keys that start with `prefix` and their values, in
+    /// lexicographic order of the keys.
+    pub fn prefix_iter<K: AsRef<[u8]>>(&self, prefix: K) -> PrefixIter<'_, V> {
+        let prefix = prefix.as_ref();
+        let stack = match self.root.find(prefix) {
+            Some(node) => vec![(prefix.to_vec(), node)],
+            None       => vec![]
+        };
+        PrefixIter { stack: stack }
+    }
+
+    pub fn iter(&self) -> PrefixIter<'_, V> {
+        self.prefix_iter(b"")
+    }
+
+    /// The longest key that is a prefix of `key`, given by its length, and
+    /// its value.
+    pub fn longest_prefix<K: AsRef<[u8]>>(&self, key: K) -> Option<(usize, &V)> {
+        let mut node = &*self.root;
+        let mut ret = node.value.as_ref().map(|v| (0, v));
+        for (i, &b) in key.as_ref().iter().enumerate() {
+            node = match node.child(b) {
+                Some(child) => child,
+                None        => break
+            };
+            if let Some(ref v) = node.value {
+                ret = Some((i + 1, v));
+            }
+        }
+        ret
+    }
+}
+
+impl <K: AsRef<[u8]>, V: Clone> FromIterator<(K, V)> for Trie<V> {
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> Trie<V> {
+        let mut ret = Trie::new();
+        for (k, v) in iter {
+            ret.insert(k, v);
+        }
+        ret
+    }
+}
+
+fn visit_node<V: Clone>(node: &Ref<Node<V>>, v: &mut NodeVisitor) {
+    if v.node_ref(node, vec_bytes(&node.children)) {
+        for &(_, ref child) in &node.children {
+            visit_node(child, v);
+        }
+    }
+}
+
+impl <V: Clone> MemoryStats for Trie<V> {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        visit_node(&self.root, v)
+    }
+}
+
+// A key as a string when it is UTF-8, and as an escaped byte string
+// otherwise.
+struct Key(Vec<u8>);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(&self.0) {
+            Ok(s)  => fmt::Debug::fmt(s, f),
+            Err(_) => write!(f, "b\"{}\"", self)
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(&self.0) {
+            Ok(s)  => f.write_str(s),
+            Err(_) => {
+                for &b in &self.0 {
+                    for c in escape_default(b) {
+                        write!(f, "{}", c as char)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// `{"a": 1, "ab": 2}` in key order, or with `{:#?}` the nodes of the trie.
+impl <V: Clone+fmt::Debug> fmt::Debug for Trie<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return f.debug_struct("Trie").field("len", &self.len).field("root", &self.root).finish()
+        }
+        f.debug_map().entries(self.iter().map(|(k, v)| (Key(k), v))).finish()
+    }
+}
+
+impl <V: Clone+fmt::Display> fmt::Display for Trie<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter().map(|(k, v)| (Disp(Key(k)), Disp(v)))).finish()
+    }
+}
+
+pub struct PrefixIter<'a, V: Clone+'a> {
+    stack : Vec<(Vec<u8>, &'a Node<V>)>,
+}
+
+impl <'a, V: Clone> Iterator for PrefixIter<'a, V> {
+    type Item = (Vec<u8>, &'a V);
+    fn next(&mut self) -> Option<(Vec<u8>, &'a V)> {
+        while let Some((key, node)) = self.stack.pop() {
+            for &(b, ref child) in node.children.iter().rev() {
+                let mut child_key = key.clone();
+                child_key.push(b);
+                self.stack.push((child_key, &**child));
+            }
+            if let Some(ref v) = node.value {
+                return Some((key, v))
+            }
+        }
+        None
+    }
+}
+
+
+#[test]
+fn trie_insert_get() {
+    let mut t = Trie::new();
+    assert!(t.is_empty());
+    assert_eq!(None, t.insert("tea", 1));
+    assert_eq!(None, t.insert("ten", 2));
+    assert_eq!(None, t.insert("to", 3));
+    assert_eq!(None, t.insert("", 0));
+    assert_eq!(Some(2), t.insert("ten", 4));
+    assert_eq!(4, t.len());
+
+    assert_eq!(Some(&1), t.get("tea"));
+    assert_eq!(Some(&4), t.get(b"ten"));
+    assert_eq!(Some(&0), t.get(""));
+    assert_eq!(None, t.get("te"));
+    assert_eq!(None, t.get("tease"));
+    assert!(t.contains_key("to"));
+}
+
+#[test]
+fn trie_remove() {
+    let mut t : Trie<i32> = vec![("a", 1), ("ab", 2), ("abc", 3), ("b", 4)].into_iter().collect();
+    let snapshot = t.clone();
+    assert_eq!(Some(3), t.remove("abc"));
+    assert_eq!(None, t.remove("abc"));
+    assert_eq!(None, t.remove("x"));
+    assert_eq!(Some(1), t.remove("a"));
+    assert_eq!(2, t.len());
+    assert_eq!(Some(&2), t.get("ab"));
+    assert_eq!(None, t.get("a"));
+
+    assert_eq!(4, snapshot.len());
+    assert_eq!(Some(&3), snapshot.get("abc"));
+    assert_eq!(Some(&1), snapshot.get("a"));
+
+    // removed branches are pruned
+    t.remove("ab");
+    t.remove("b");
+    assert!(t.root.is_empty());
+}
+
+#[test]
+fn trie_prefix_iter() {
+    let t : Trie<i32> = vec![("print", 1), ("println", 2), ("panic", 3), ("pri", 4), ("assert", 5)]
+        .into_iter().collect();
+    let keys : Vec<_> = t.prefix_iter("pri").map(|(k, v)| (String::from_utf8(k).unwrap(), *v)).collect();
+    assert_eq!(vec![("pri".to_string(), 4), ("print".to_string(), 1), ("println".to_string(), 2)], keys);
+    assert_eq!(0, t.prefix_iter("q").count());
+    let all : Vec<_> = t.iter().map(|(k, _)| k).collect();
+    assert_eq!(vec![b"assert".to_vec(), b"panic".to_vec(), b"pri".to_vec(), b"print".to_vec(), b"println".to_vec()], all);
+}
+
+#[test]
+fn trie_longest_prefix() {
+    let mut routes = Trie::new();
+    routes.insert(&[10u8][..], "a");
+    routes.insert(&[10u8, 1][..], "b");
+    routes.insert(&[10u8, 1, 2, 3][..], "c");
+
+    assert_eq!(Some((2, &"b")), routes.longest_prefix(&[10u8, 1, 2][..]));
+    assert_eq!(Some((4, &"c")), routes.longest_prefix(&[10u8, 1, 2, 3, 4][..]));
+    assert_eq!(Some((1, &"a")), routes.longest_prefix(&[10u8, 7][..]));
+    assert_eq!(None, routes.longest_prefix(&[11u8][..]));
+
+    routes.insert("", "default");
+    assert_eq!(Some((0, &"default")), routes.longest_prefix(&[11u8][..]));
+}
+
+#[test]
+fn trie_fmt() {
+    let mut t : Trie<i32> = vec![("b", 2), ("a", 1)].into_iter().collect();
+    assert_eq!("{\"a\": 1, \"b\": 2}", format!("{:?}", t));
+    assert_eq!("{a: 1, b: 2}", format!("{}", t));
+    t.insert(&[0xff, b'c'][..], 3);
+    assert_eq!("{\"a\": 1, \"b\": 2, b\"\\xffc\": 3}", format!("{:?}", t));
+    assert!(format!("{:#?}", t).starts_with("Trie {\n    len: 3,"));
+    assert_eq!("{}", format!("{:?}", Trie::<i32>::new()));
+}
@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::replace;
+use std::rc::Rc;
+
+use stats::{MemoryStats, NodeVisitor, vec_bytes};
+
+// A persistent array in Baker's style: the newest version owns a plain vector
+// and every other version is a chain of differences leading to it. Reading a
+// version first "reroots" it, reversing the differences on the way, so that
+// the version being worked on is always O(1) to access. Like the suspensions
+// in `realtime_deque`, the nodes are updated in place and can not sit on
+// giftr's `Ref`: rerooting must be visible to every version sharing them.
+enum Data<T> {
+    Arr(Vec<T>),
+    Diff(usize, T, Link<T>),
+}
+
+type Link<T> = Rc<RefCell<Data<T>>>;
+
+struct PArray<T: Clone> {
+    link : Link<T>,
+}
+
+impl <T: Clone> PArray<T> {
+    fn new(xs: Vec<T>) -> PArray<T> {
+        PArray { link: Rc::new(RefCell::new(Data::Arr(xs))) }
+    }
+
+    // Makes this version own the vector. Iterative, since old versions can
+    // be arbitrarily far away from the newest one.
+    fn reroot(&self) {
+        let mut path = vec![self.link.clone()];
+        loop {
+            let next = match *path[path.len() - 1].borrow() {
+                Data::Arr(_)             => None,
+                Data::Diff(_, _, ref to) => Some(to.clone())
+            };
+            match next {
+                Some(next) => path.push(next),
+                None       => break
+            }
+        }
+        while path.len() > 1 {
+            let root = path.pop().unwrap();
+            let node = &path[path.len() - 1];
+            let mut xs = match replace(&mut *root.borrow_mut(), Data::Arr(vec![])) {
+                Data::Arr(xs) => xs,
+                Data::Diff(..) => unreachable!()
+            };
+            let (i, x) = match replace(&mut *node.borrow_mut(), Data::Arr(vec![])) {
+                Data::Diff(i, x, _) => (i, x),
+                Data::Arr(_)        => unreachable!()
+            };
+            let old = replace(&mut xs[i], x);
+            *root.borrow_mut() = Data::Diff(i, old, node.clone());
+            *node.borrow_mut() = Data::Arr(xs);
+        }
+    }
+
+    fn get(&self, i: usize) -> T {
+        self.reroot();
+        match *self.link.borrow() {
+            Data::Arr(ref xs) => xs[i].clone(),
+            Data::Diff(..)    => unreachable!()
+        }
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.reroot();
+        match *self.link.borrow() {
+            Data::Arr(ref xs) => xs.clone(),
+            Data::Diff(..)    => unreachable!()
+        }
+    }
+
+    // A new version with `xs[i] = x`; `self` keeps its contents.
+    fn set(&self, i: usize, x: T) -> PArray<T> {
+        self.reroot();
+        let mut xs = match replace(&mut *self.link.borrow_mut(), Data::Arr(vec![])) {
+            Data::Arr(xs)  => xs,
+            Data::Diff(..) => unreachable!()
+        };
+        let old = replace(&mut xs[i], x);
+        let ret = PArray::new(xs);
+        *self.link.borrow_mut() = Data::Diff(i, old, ret.link.clone());
+        ret
+    }
+}
+
+impl <T: Clone> PArray<T> {
+    // Walks the differences to the vector without rerooting, so that
+    // inspecting a version does not disturb the one being worked on.
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        let mut cur = self.link.clone();
+        loop {
+            let next = {
+                let data = cur.borrow();
+                let extra = match *data {
+                    Data::Arr(ref xs) => vec_bytes(xs),
+                    Data::Diff(..)    => 0
+                };
+                // the walk holds a count of its own
+                if !v.node(&*cur, Rc::strong_count(&cur) - 1, extra) {
+                    return
+                }
+                match *data {
+                    Data::Arr(_)             => return,
+                    Data::Diff(_, _, ref to) => to.clone()
+                }
+            };
+            cur = next;
+        }
+    }
+}
+
+impl <T: Clone> Clone for PArray<T> {
+    fn clone(&self) -> PArray<T> {
+        PArray { link: self.link.clone() }
+    }
+}
+
+impl <T: Clone> Drop for PArray<T> {
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.link) > 1 {
+            return
+        }
+        let mut optcur = match replace(&mut *self.link.borrow_mut(), Data::Arr(vec![])) {
+            Data::Diff(_, _, next) => Some(next),
+            Data::Arr(_)           => None
+        };
+        while let Some(cur) = optcur {
+            optcur = match Rc::try_unwrap(cur) {
+                Ok(cell) => match cell.into_inner() {
+                    Data::Diff(_, _, next) => Some(next),
+                    Data::Arr(_)           => None
+                },
+                Err(_) => None
+            }
+        }
+    }
+}
+
+/// A persistent disjoint-set forest over the elements `0..len` (Conchon and
+/// Filliâtre, "A Persistent Union-Find Data Structure"), with union by rank
+/// and path compression. Clones are O(1) and every version stays valid, so
+/// a clone is a snapshot to backtrack to. Operations are fast on the version
+/// last worked on; switching to another version costs time proportional to
+/// the number of updates between them.
+#[derive(Clone)]
+pub struct UnionFind {
+    rank   : PArray<usize>,
+    // compressing paths does not change the sets, so `find` does it through
+    // a shared reference
+    parent : RefCell<PArray<usize>>,
+    len    : usize,
+    sets   : usize,
+}
+
+impl UnionFind {
+    /// `len` elements, each in a set of its own.
+    pub fn new(len: usize) -> UnionFind {
+        UnionFind {
+            rank: PArray::new(vec![0; len]),
+            parent: RefCell::new(PArray::new((0..len).collect())),
+            len: len,
+            sets: len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn num_sets(&self) -> usize {
+        self.sets
+    }
+
+    /// The representative of the set of `x`. Panics if `x >= len`.
+    pub fn find(&self, x: usize) -> usize {
+        assert!(x < self.len, "union find: element out of bounds");
+        let mut parent = self.parent.borrow_mut();
+        let mut path = vec![];
+        let mut root = x;
+        loop {
+            let next = parent.get(root);
+            if next == root {
+                break
+            }
+            path.push(root);
+            root = next;
+        }
+        path.pop();
+        for i in path {
+            let compressed = parent.set(i, root);
+            *parent = compressed;
+        }
+        root
+    }
+
+    pub fn same_set(&self, x: usize, y: usize) -> bool {
+        self.find(x) == self.find(y)
+    }
+
+    /// Merges the sets of `x` and `y`; returns false if they already were
+    /// the same set.
+    pub fn union(&mut self, x: usize, y: usize) -> bool {
+        let (rx, ry) = (self.find(x), self.find(y));
+        if rx == ry {
+            return false
+        }
+        let (kx, ky) = (self.rank.get(rx), self.rank.get(ry));
+        let parent = self.parent.get_mut();
+        if kx > ky {
+            *parent = parent.set(ry, rx);
+        } else {
+            *parent = parent.set(rx, ry);
+            if kx == ky {
+                self.rank = self.rank.set(ry, ky + 1);
+            }
+        }
+        self.sets -= 1;
+        true
+    }
+
+    // The sets in the order of their smallest elements.
+    fn sets(&self) -> Vec<Vec<usize>> {
+        let mut ret : Vec<Vec<usize>> = vec![];
+        let mut index = HashMap::new();
+        for x in 0..self.len {
+            let i = *index.entry(self.find(x)).or_insert(ret.len());
+            if i == ret.len() {
+                ret.push(vec![]);
+            }
+            ret[i].push(x);
+        }
+        ret
+    }
+}
+
+/// `UnionFind{len: 10, sets: 7}`, or with `{:#?}` the sets and the arrays.
+impl fmt::Debug for UnionFind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "UnionFind{{len: {}, sets: {}}}", self.len, self.sets)
+        }
+        f.debug_struct("UnionFind")
+            .field("len", &self.len)
+            .field("sets", &self.sets())
+            .field("parent", &self.parent.borrow().to_vec())
+            .field("rank", &self.rank.to_vec())
+            .finish()
+    }
+}
+
+/// The sets, as in `{{0, 1}, {2}}`.
+impl fmt::Display for UnionFind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sets = self.sets();
+        f.debug_set().entries(sets.iter().map(|s| Set(&s[..]))).finish()
+    }
+}
+
+struct Set<'a>(&'a [usize]);
+
+impl <'a> fmt::Debug for Set<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.0).finish()
+    }
+}
+
+impl MemoryStats for UnionFind {
+    fn visit_nodes(&self, v: &mut NodeVisitor) {
+        self.rank.visit_nodes(v);
+        self.parent.borrow().visit_nodes(v)
+    }
+}
+
+
+#[test]
+fn uf_union_find() {
+    let mut uf = UnionFind::new(10);
+    assert_eq!(10, uf.num_sets());
+    assert!(!uf.same_set(1, 2));
+    assert!(uf.union(1, 2));
+    assert!(uf.union(3, 4));
+    assert!(uf.union(2, 4));
+    assert!(!uf.union(1, 3));
+    assert_eq!(7, uf.num_sets());
+    assert!(uf.same_set(1, 3));
+    assert_eq!(uf.find(1), uf.find(4));
+    assert!(!uf.same_set(1, 5));
+    assert_eq!(10, uf.len());
+}
+
+#[test]
+fn uf_fmt() {
+    let mut uf = UnionFind::new(4);
+    uf.union(3, 1);
+    let snapshot = uf.clone();
+    uf.union(0, 2);
+    assert_eq!("UnionFind{len: 4, sets: 2}", format!("{:?}", uf));
+    assert_eq!("{{0, 2}, {1, 3}}", format!("{}", uf));
+    assert_eq!("{{0}, {1, 3}, {2}}", format!("{}", snapshot));
+    assert_eq!("{{0, 2}, {1, 3}}", format!("{}", uf));
+    assert!(format!("{:#?}", uf).contains("rank: ["));
+}
+
+#[test]
+fn uf_snapshots() {
+    let mut uf = UnionFind::new(6);
+    uf.union(0, 1);
+    let snapshot = uf.clone();
+    uf.union(1, 2);
+    uf.union(3, 4);
+    let mut branch = snapshot.clone();
+    branch.union(0, 5);
+
+    assert!(uf.same_set(0, 2));
+    assert!(!snapshot.same_set(0, 2));
+    assert!(!snapshot.same_set(3, 4));
+    assert!(snapshot.same_set(0, 1));
+    assert!(branch.same_set(1, 5));
+    assert!(!uf.same_set(1, 5));
+    assert!(!branch.same_set(0, 2));
+    assert_eq!(3, uf.num_sets());
+    assert_eq!(5, snapshot.num_sets());
+    assert_eq!(4, branch.num_sets());
+    // switching back and forth keeps every version intact
+    for _ in 0..3 {
+        assert!(uf.same_set(3, 4));
+        assert!(!branch.same_set(3, 4));
+    }
+}
+
+#[test]
+fn uf_long_history() {
+    let n = 100000;
+    let mut uf = UnionFind::new(n);
+    let first = uf.clone();
+    for i in 1..n {
+        uf.union(i - 1, i);
+    }
+    assert_eq!(1, uf.num_sets());
+    assert!(uf.same_set(0, n - 1));
+    assert!(!first.same_set(0, n - 1));
+    assert!(uf.same_set(0, n - 1));
+    drop(uf);
+    assert_eq!(n, first.num_sets());
+}